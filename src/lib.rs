@@ -1,37 +1,168 @@
+use std::error::Error;
+use std::fmt;
 use std::io;
+use std::ops::{BitOr, Index};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[macro_use]
+mod macros;
+#[cfg(unix)]
+pub mod runtime;
+mod slab;
+
+pub use slab::TokenSlab;
 
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
-pub use linux::{Event, Registrator, Selector, TcpStream};
+pub use linux::{
+    Backend, Clock, Event, Expiration, Registrator, Selector, SignalInfo, Signals, TcpListener,
+    TcpStream, Timer, UdpSocket, UnixDatagram, UnixListener, UnixStream, Waker,
+};
 
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(target_os = "macos")]
-pub use macos::{Event, Registrator, Selector, TcpStream};
+pub use macos::{
+    Event, Registrator, Selector, TcpListener, TcpStream, UdpSocket, UnixDatagram, UnixListener,
+    UnixStream, Waker,
+};
 
 #[cfg(target_os = "windows")]
 mod windows;
 #[cfg(target_os = "windows")]
-pub use windows::{Event, Registrator, Selector, TcpStream};
+pub use windows::{Event, Registrator, Selector, TcpListener, TcpStream, UdpSocket, Waker};
+
+// 存放一次 poll 返回的就绪事件，内核直接把事件写进这里的缓冲区，每次最多写入 capacity 个，
+// 更多的就绪事件留在内核中等下一次 poll 取走
+#[derive(Debug)]
+pub struct Events {
+    inner: Vec<Event>,
+    growable: bool,
+}
+
+impl Events {
+    // epoll_wait 要求 maxevents 大于 0, 所以容量至少为 1
+    pub fn with_capacity(capacity: usize) -> Events {
+        Events {
+            inner: Vec::with_capacity(capacity.max(1)),
+            growable: false,
+        }
+    }
+
+    // 一次 poll 把缓冲区填满时说明可能还有事件没有取到，容量翻倍，下一次 poll 可以取到更多事件
+    pub fn growable(capacity: usize) -> Events {
+        Events {
+            growable: true,
+            ..Events::with_capacity(capacity)
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Event> {
+        self.inner.get(index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Event> {
+        self.inner.iter()
+    }
 
-pub type Events = Vec<Event>;
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    // Selector 通过这个缓冲区接收内核写入的事件
+    pub(crate) fn inner_mut(&mut self) -> &mut Vec<Event> {
+        &mut self.inner
+    }
+
+    fn grow_if_full(&mut self) {
+        if self.growable && self.inner.len() == self.inner.capacity() {
+            self.inner.reserve(self.inner.capacity());
+        }
+    }
+}
+
+impl Index<usize> for Events {
+    type Output = Event;
+
+    fn index(&self, index: usize) -> &Event {
+        &self.inner[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a Events {
+    type Item = &'a Event;
+    type IntoIter = std::slice::Iter<'a, Event>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// 标识事件源的数字，注册时传入，收到事件时通过 `Event::id` 取回。
+/// `usize::MAX` 保留给 `close_loop` 唤醒 `poll` 使用，不能用来注册。
 pub type Token = usize;
 
+// close_loop 唤醒 poll 时使用的 token, Selector 不会把它的事件交给用户
+pub(crate) const CLOSE_TOKEN: Token = slab::reserved(0);
+
+pub(crate) fn check_token(token: Token) -> io::Result<()> {
+    if token == CLOSE_TOKEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "token usize::MAX is reserved",
+        ));
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct Poll {
     registry: Registry,
     is_poll_dead: Arc<AtomicBool>,
+    // close_loop 之后已经交出过最后一次收集到的事件
+    drained: bool,
 }
 
 impl Poll {
     pub fn new() -> io::Result<Poll> {
-        Selector::new().map(|selector| Poll {
+        Selector::new().map(Poll::from_selector)
+    }
+
+    // 指定 Linux 上使用的事件通知机制，比如 epoll 被 seccomp 拦截时使用 `Backend::Poll`
+    #[cfg(target_os = "linux")]
+    pub fn with_backend(backend: Backend) -> io::Result<Poll> {
+        Selector::with_backend(backend).map(Poll::from_selector)
+    }
+
+    // 实际使用的事件通知机制，打开 `io-uring` feature 时可以用来确认是否退回了 epoll
+    #[cfg(target_os = "linux")]
+    pub fn backend(&self) -> Backend {
+        self.registry.selector.backend()
+    }
+
+    fn from_selector(selector: Selector) -> Poll {
+        Poll {
             registry: Registry { selector },
             is_poll_dead: Arc::new(AtomicBool::new(false)),
-        })
+            drained: false,
+        }
     }
 
     pub fn registrator(&self) -> Registrator {
@@ -39,36 +170,137 @@ impl Poll {
             .selector
             .registrator(self.is_poll_dead.clone())
     }
-    pub fn poll(&mut self, events: &mut Events, timeout_ms: Option<i32>) -> io::Result<usize> {
-        let timeout = timeout_ms.map(|n| if n < 0 { 0 } else { n });
+    // 是否已经通过 close_loop 关闭
+    pub fn is_closed(&self) -> bool {
+        self.is_poll_dead.load(Ordering::SeqCst)
+    }
+
+    // timeout 为 None 时一直阻塞到有事件发生，超时精度由 Selector 决定，但不会比 timeout 提前返回
+    pub fn poll(&mut self, events: &mut Events, timeout: Option<Duration>) -> io::Result<usize> {
+        if self.drained {
+            events.clear();
+            return Err(closed());
+        }
+
+        // 被信号打断后重试时只等待剩下的时间，否则频繁收到信号的进程会一直阻塞下去
+        // timeout 大到算不出截止时间时当作一直阻塞
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut timeout = timeout;
         loop {
             let res = self.registry.selector.select(events, timeout);
             match res {
                 Ok(()) => break,
-                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
-                Err(e) => return Err(e),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                    trace!("poll interrupted, retrying");
+                    if let Some(deadline) = deadline {
+                        timeout = Some(deadline.saturating_duration_since(Instant::now()));
+                    }
+                }
+                Err(e) => {
+                    error!("poll failed: {}", e);
+                    return Err(e);
+                }
             };
         }
 
-        if self.is_poll_dead.load(Ordering::SeqCst) {
-            return Err(io::Error::new(io::ErrorKind::Interrupted, "Poll closed."));
+        // close_loop 唤醒的这一次仍然交出同时收集到的事件，下一次 poll 才报告关闭
+        if self.is_closed() {
+            self.drained = true;
+            if events.is_empty() {
+                return Err(closed());
+            }
         }
+        events.grow_if_full();
         Ok(events.len())
     }
 }
 
+// Registrator 可能比 Poll 活得更久，它持有的 Selector 实现不会被关闭，
+// 但是已经没有人 poll 了，这里标记为关闭，之后的注册返回关闭的错误
+impl Drop for Poll {
+    fn drop(&mut self) {
+        self.is_poll_dead.store(true, Ordering::SeqCst);
+    }
+}
+
+// Poll 被 close_loop 关闭之后返回的错误，不能用 Interrupted, 否则和信号打断（EINTR）分不开
+#[derive(Debug)]
+struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Poll instance closed")
+    }
+}
+
+impl Error for Closed {}
+
+pub(crate) fn closed() -> io::Error {
+    io::Error::other(Closed)
+}
+
+/// `Poll::poll`、`Registrator` 的各个方法是不是因为 `Poll` 已经关闭（`close_loop` 或者 `Poll` 被释放）才返回错误。
+///
+/// 这个错误的 `ErrorKind` 是 `Other`, 事件循环应该用这个函数判断是否退出，
+/// 而不是 `ErrorKind::Interrupted`（`poll` 被信号打断时会自己重试，不会返回 Interrupted）。
+pub fn is_closed(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|err| err.is::<Closed>())
+}
+
 #[derive(Debug)]
 pub struct Registry {
     selector: Selector,
 }
 
+/// 可以注册到 `Poll` 的事件源。
+///
+/// tinymio 自己的类型都实现了这个 trait, 其他 crate 里基于 fd 的类型只要返回自己的 fd 就可以注册,
+/// 临时注册一个裸 fd 可以使用 `SourceFd`。实现者需要保证注册期间 fd 一直有效，
+/// 关闭 fd 之前最好先 `deregister`。
+#[cfg(unix)]
+pub trait Source {
+    fn raw_fd(&self) -> RawFd;
+}
+
+/// 把任意 fd 包装成 `Source`, 比如 `SourceFd(&stream.as_raw_fd())`。
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct SourceFd<'a>(pub &'a RawFd);
+
+#[cfg(unix)]
+impl Source for SourceFd<'_> {
+    fn raw_fd(&self) -> RawFd {
+        *self.0
+    }
+}
+
 const WRITABLE: u8 = 0b0000_0001;
 const READABLE: u8 = 0b0000_0010;
+const LEVEL: u8 = 0b0000_0100;
+const EDGE: u8 = 0b0000_1000;
+
+/// 事件的触发模式，通过 `Interests::LEVEL` / `Interests::EDGE` 和读写兴趣组合在一起注册,
+/// 两者都没有设置时默认是 `Oneshot`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// 水平触发：只要 fd 仍然就绪，每次 `poll` 都会返回事件。
+    /// 不需要一次读完，但是不处理的话下一次 `poll` 会立刻返回，不想继续收到事件时需要 `deregister`。
+    Level,
+    /// 边沿触发：只在就绪状态变化（新数据到达、发送缓冲区腾出空间）时返回一次事件，注册一直有效。
+    /// 收到事件后必须一直读/写到返回 `WouldBlock` 为止，否则剩下的数据不会再有事件通知。
+    Edge,
+    /// 一次性触发：第一个事件之后这个 fd 被禁用但仍然留在队列中，
+    /// 处理完之后需要调用 `reregister` 重新打开监听，不需要读到 `WouldBlock`。
+    Oneshot,
+}
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Interests(u8);
 impl Interests {
     pub const READABLE: Interests = Interests(READABLE);
     pub const WRITABLE: Interests = Interests(WRITABLE);
+    pub const LEVEL: Interests = Interests(LEVEL);
+    pub const EDGE: Interests = Interests(EDGE);
 
     pub fn is_readable(&self) -> bool {
         self.0 & READABLE != 0
@@ -77,4 +309,24 @@ impl Interests {
     pub fn is_writable(&self) -> bool {
         self.0 & WRITABLE != 0
     }
+
+    // LEVEL 和 EDGE 同时设置时以 EDGE 为准
+    pub fn trigger(&self) -> Trigger {
+        if self.0 & EDGE != 0 {
+            Trigger::Edge
+        } else if self.0 & LEVEL != 0 {
+            Trigger::Level
+        } else {
+            Trigger::Oneshot
+        }
+    }
+}
+
+// 支持 Interests::READABLE | Interests::WRITABLE 一次注册读写两种兴趣
+impl BitOr for Interests {
+    type Output = Interests;
+
+    fn bitor(self, other: Interests) -> Interests {
+        Interests(self.0 | other.0)
+    }
 }
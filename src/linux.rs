@@ -1,172 +1,1322 @@
-use crate::{Events, Interests, Token};
+use crate::runtime::Registration;
+use crate::{Events, Interests, Poll, Source, Token, Trigger};
+use std::ffi::c_void;
+use std::fmt;
+use std::fs::File;
 use std::io::{self, IoSliceMut, Read, Write};
+use std::mem::{self, ManuallyDrop};
 use std::net;
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net as unix;
+use std::path::Path;
+use std::ptr;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU8, Ordering},
     Arc,
 };
+use std::time::Duration;
+
+mod poll;
+#[cfg(feature = "io-uring")]
+mod uring;
 
 pub struct Registrator {
-    epoll_fd: RawFd,
+    driver: Driver,
+    // close_loop 通过它唤醒阻塞在 poll 上的线程
+    close: Arc<File>,
     is_poll_dead: Arc<AtomicBool>,
 }
 
-impl Registrator {
-    // 封装ffi epoll_crate 提供rust的事件注册功能
-    pub fn register(
-        &self,
-        stream: &TcpStream,
-        token: usize,
-        interests: Interests,
-    ) -> io::Result<()> {
-        // 检查是否关闭
-        if self.is_poll_dead.load(Ordering::SeqCst) {
+impl Registrator {
+    // 封装ffi epoll_crate 提供rust的事件注册功能
+    pub fn register<S: Source + ?Sized>(
+        &self,
+        source: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        // 检查是否关闭
+        self.check_alive()?;
+        crate::check_token(token)?;
+
+        // 获取事件源的fd
+        let fd = source.raw_fd();
+        // 然后注册对接收此套接字上 `Read`/`Write` 事件通知的兴趣。`Event` 结构体用于指定要注册兴趣的事件以及其他使用标志的配置。
+        //
+        // `EPOLLIN` 表示对 `Read` 事件的兴趣。
+        // `EPOLLOUT` 表示对 `Write` 事件的兴趣，发送缓冲区有空间或者非阻塞 connect 完成时触发。
+        // 触发模式由 `Interests::trigger` 决定:
+        //  - `EPOLLONESHOT` 表示在第一个事件之后从队列中移除所有兴趣。如果不这样做，我们需要在套接字处理完毕后手动 `deregister` 我们的兴趣。
+        //  - `EPOLLET` 表示边沿触发，只在状态变化时通知一次
+        //  - 都不设置就是 epoll 默认的水平触发
+        //
+        // 读写兴趣合并到同一个 `Event` 里一次注册，同一个 fd 在 epoll 中只能 ADD 一次。
+        //
+        // `epoll_data` 是用户提供的数据，因此我们可以在其中放置一个指针或整数值来标识事件。我们仅使用“i”即循环计数来识别事件。
+        debug!(
+            "register {:?} fd={} token={} interests={:?}",
+            self.driver, fd, token, interests
+        );
+        self.driver
+            .add(fd, token, interests)
+            .map_err(|e| ctl_error("register", fd, e))
+    }
+
+    // oneshot 事件触发之后 fd 仍然留在 epoll 中，只是被禁用了，通过 EPOLL_CTL_MOD 重新打开监听
+    // 也可以用来修改已注册 fd 的 token 和 interests
+    pub fn reregister<S: Source + ?Sized>(
+        &self,
+        source: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.check_alive()?;
+        crate::check_token(token)?;
+
+        let fd = source.raw_fd();
+        debug!(
+            "reregister {:?} fd={} token={} interests={:?}",
+            self.driver, fd, token, interests
+        );
+        self.driver
+            .modify(fd, token, interests)
+            .map_err(|e| ctl_error("reregister", fd, e))
+    }
+
+    // 从 epoll 中移除 fd, 之后不会再收到这个 fd 的任何事件
+    pub fn deregister<S: Source + ?Sized>(&self, source: &S) -> io::Result<()> {
+        self.check_alive()?;
+
+        let fd = source.raw_fd();
+        debug!("deregister {:?} fd={}", self.driver, fd);
+        self.driver
+            .delete(fd)
+            .map_err(|e| ctl_error("deregister", fd, e))
+    }
+
+    // 对应的 Poll 是否已经通过 close_loop 关闭
+    pub fn is_closed(&self) -> bool {
+        self.is_poll_dead.load(Ordering::SeqCst)
+    }
+
+    fn check_alive(&self) -> io::Result<()> {
+        if self.is_closed() {
+            return Err(crate::closed());
+        }
+        Ok(())
+    }
+
+    // 将is_poll_dead设置为true之后，写一下 Selector 的 close eventfd 唤醒 poll,
+    // 这次唤醒收集到的其他事件仍然会交给用户，之后的 poll 返回关闭的错误
+    pub fn close_loop(&self) -> io::Result<()> {
+        if self
+            .is_poll_dead
+            // .compare_and_swap(false, true, Ordering::SeqCst)
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(crate::closed());
+        }
+
+        debug!("close_loop {:?}", self.driver);
+        (&*self.close).write_all(&1u64.to_ne_bytes())
+    }
+}
+
+// epoll_ctl 的 EEXIST/ENOENT 只会告诉我们 "File exists"/"No such file or directory"
+// 这里换成和注册相关的错误信息，ErrorKind 保持不变
+fn ctl_error(op: &str, fd: RawFd, err: io::Error) -> io::Error {
+    error!("{} fd={} failed: {}", op, fd, err);
+    match err.kind() {
+        io::ErrorKind::AlreadyExists => {
+            io::Error::new(io::ErrorKind::AlreadyExists, "source is already registered")
+        }
+        io::ErrorKind::NotFound => {
+            io::Error::new(io::ErrorKind::NotFound, "source is not registered")
+        }
+        _ => err,
+    }
+}
+
+// 把 Interests 转换成 epoll 的事件掩码
+fn epoll_events(interests: Interests) -> i32 {
+    let mut events = match interests.trigger() {
+        Trigger::Level => 0,
+        Trigger::Edge => ffi::EPOLLET,
+        Trigger::Oneshot => ffi::EPOLLONESHOT,
+    };
+    if interests.is_readable() {
+        // 同时关心对端半关闭，这样 Event::is_read_closed 才能拿到 EPOLLRDHUP
+        events |= ffi::EPOLLIN | ffi::EPOLLRDHUP;
+    }
+    if interests.is_writable() {
+        events |= ffi::EPOLLOUT;
+    }
+    events
+}
+
+// Selector 的具体实现，Registrator 持有同一个实现的句柄
+// 所有后端都通过 Arc 共享，Registrator 还在的时候 fd 不会被关闭，
+// 不会出现对已经关闭、甚至被其他文件复用的 fd 号调用 epoll_ctl 的情况
+#[derive(Debug, Clone)]
+enum Driver {
+    Epoll(Arc<EpollFd>),
+    Poll(Arc<poll::PollSet>),
+    #[cfg(feature = "io-uring")]
+    Uring(Arc<uring::Ring>),
+}
+
+impl Driver {
+    fn add(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        match self {
+            Driver::Epoll(epoll_fd) => {
+                let mut event = ffi::Event::new(epoll_events(interests), token);
+                epoll_ctl(epoll_fd.0, ffi::EPOLL_CTL_ADD, fd, &mut event)
+            }
+            Driver::Poll(poll_set) => poll_set.add(fd, token, interests),
+            #[cfg(feature = "io-uring")]
+            Driver::Uring(ring) => ring.add(fd, token, interests),
+        }
+    }
+
+    fn modify(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        match self {
+            Driver::Epoll(epoll_fd) => {
+                let mut event = ffi::Event::new(epoll_events(interests), token);
+                epoll_ctl(epoll_fd.0, ffi::EPOLL_CTL_MOD, fd, &mut event)
+            }
+            Driver::Poll(poll_set) => poll_set.modify(fd, token, interests),
+            #[cfg(feature = "io-uring")]
+            Driver::Uring(ring) => ring.modify(fd, token, interests),
+        }
+    }
+
+    fn delete(&self, fd: RawFd) -> io::Result<()> {
+        match self {
+            Driver::Epoll(epoll_fd) => {
+                // 2.6.9 之前的内核要求 EPOLL_CTL_DEL 也传一个非空的 event
+                let mut event = ffi::Event::new(0, 0);
+                epoll_ctl(epoll_fd.0, ffi::EPOLL_CTL_DEL, fd, &mut event)
+            }
+            Driver::Poll(poll_set) => poll_set.delete(fd),
+            #[cfg(feature = "io-uring")]
+            Driver::Uring(ring) => ring.delete(fd),
+        }
+    }
+
+    // Waker 的 eventfd 按边沿触发注册，写入之后不需要读出计数器
+    fn add_waker(&self, fd: RawFd, token: Token) -> io::Result<()> {
+        match self {
+            // poll(2) 只有水平触发，由 PollSet 负责清零计数器
+            Driver::Poll(poll_set) => poll_set.add_waker(fd, token),
+            _ => self.add(fd, token, Interests::READABLE | Interests::EDGE),
+        }
+    }
+
+    fn backend(&self) -> Backend {
+        match self {
+            Driver::Epoll(..) => Backend::Epoll,
+            Driver::Poll(..) => Backend::Poll,
+            #[cfg(feature = "io-uring")]
+            Driver::Uring(..) => Backend::IoUring,
+        }
+    }
+}
+
+/// Linux 上可以选择的事件通知机制，通过 `Poll::with_backend` 指定。
+///
+/// `Poll::new` 默认使用 epoll; 打开 `io-uring` feature 时优先使用 io_uring, 内核不支持时退回 epoll;
+/// 打开 `poll-backend` feature 时默认使用 poll(2)。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    Epoll,
+    /// 不依赖 epoll, 适合 epoll 被 seccomp 拦截的环境。边沿触发是模拟出来的，
+    /// 需要一直读写到 WouldBlock 才能保证收到后续的事件，只读了一部分时可能会多收到一次事件
+    Poll,
+    #[cfg(feature = "io-uring")]
+    IoUring,
+}
+
+#[derive(Debug)]
+pub struct Selector {
+    driver: Driver,
+    // 用 CLOSE_TOKEN 注册的 eventfd, 所有 Registrator 共享，最后一个释放时关闭
+    close: Arc<File>,
+}
+
+impl Selector {
+    pub fn new() -> io::Result<Self> {
+        if cfg!(feature = "poll-backend") {
+            return Selector::with_backend(Backend::Poll);
+        }
+
+        // 打开 `io-uring` feature 时优先使用 io_uring, 内核不支持（或者被禁用）时退回 epoll
+        #[cfg(feature = "io-uring")]
+        match uring::Ring::new() {
+            Ok(ring) => return Selector::from_driver(Driver::Uring(Arc::new(ring))),
+            Err(e) => {
+                debug!("io_uring unavailable, falling back to epoll: {}", e);
+            }
+        }
+
+        Selector::with_backend(Backend::Epoll)
+    }
+
+    // 明确指定的后端不可用时直接返回错误，不会退回其他后端
+    pub fn with_backend(backend: Backend) -> io::Result<Self> {
+        let driver = match backend {
+            Backend::Epoll => Driver::Epoll(Arc::new(EpollFd(epoll_create()?))),
+            Backend::Poll => Driver::Poll(Arc::new(poll::PollSet::new()?)),
+            #[cfg(feature = "io-uring")]
+            Backend::IoUring => Driver::Uring(Arc::new(uring::Ring::new()?)),
+        };
+        Selector::from_driver(driver)
+    }
+
+    fn from_driver(driver: Driver) -> io::Result<Self> {
+        let close = eventfd(0, ffi::EFD_NONBLOCK | ffi::EFD_CLOEXEC)?;
+        let close = unsafe { File::from_raw_fd(close) };
+        driver.add_waker(close.as_raw_fd(), crate::CLOSE_TOKEN)?;
+        Ok(Selector {
+            driver,
+            close: Arc::new(close),
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.driver.backend()
+    }
+
+    pub fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        let res = match &self.driver {
+            Driver::Epoll(epoll_fd) => epoll_select(epoll_fd.0, events, timeout),
+            Driver::Poll(poll_set) => poll_set.select(events.inner_mut(), timeout),
+            #[cfg(feature = "io-uring")]
+            Driver::Uring(ring) => ring.select(events.inner_mut(), timeout),
+        };
+        // close_loop 的唤醒事件不交给用户
+        events
+            .inner_mut()
+            .retain(|event| event.id() != crate::CLOSE_TOKEN);
+        res
+    }
+
+    pub fn registrator(&self, is_poll_dead: Arc<AtomicBool>) -> Registrator {
+        Registrator {
+            driver: self.driver.clone(),
+            close: self.close.clone(),
+            is_poll_dead,
+        }
+    }
+}
+
+// epoll 实例的 fd, 最后一个持有者（Selector 或者 Registrator）释放时关闭
+#[derive(Debug)]
+struct EpollFd(RawFd);
+
+impl Drop for EpollFd {
+    fn drop(&mut self) {
+        match close(self.0) {
+            Ok(..) => (),
+            Err(e) => {
+                if !std::thread::panicking() {
+                    panic!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+fn epoll_select(epoll_fd: RawFd, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+    let events = events.inner_mut();
+    events.clear();
+    // 按缓冲区的实际容量限制内核写入的事件数量，避免写越界
+    let max_events = events.capacity().min(i32::MAX as usize) as i32;
+    trace!(
+        "epoll_wait epfd={} max_events={} timeout={:?}",
+        epoll_fd,
+        max_events,
+        timeout
+    );
+    epoll_wait_timeout(epoll_fd, events, max_events, timeout).map(|n_events| {
+        trace!("epoll_wait epfd={} got {} events", epoll_fd, n_events);
+        unsafe { events.set_len(n_events as usize) };
+    })
+}
+
+// Waker 用来从其他线程唤醒阻塞在 Poll::poll 上的线程，和 close_loop 不同，它不会关闭 Poll，可以重复调用
+// 内部是一个以边沿触发注册到 epoll 的非阻塞 eventfd, 每次写入都会让计数器变化，从而产生一个新的事件，
+// 所以不需要读出计数器来清除就绪状态
+#[derive(Debug)]
+pub struct Waker {
+    driver: Driver,
+    fd: File,
+}
+
+impl Waker {
+    pub fn new(poll: &Poll, token: Token) -> io::Result<Waker> {
+        let registrator = poll.registrator();
+        registrator.check_alive()?;
+        crate::check_token(token)?;
+
+        let fd = eventfd(0, ffi::EFD_NONBLOCK | ffi::EFD_CLOEXEC)?;
+        // 交给 File 管理 fd 的生命周期，注册失败时也会被关闭
+        let fd = unsafe { File::from_raw_fd(fd) };
+        debug!("waker fd={} token={}", fd.as_raw_fd(), token);
+        registrator.driver.add_waker(fd.as_raw_fd(), token)?;
+
+        Ok(Waker {
+            driver: registrator.driver.clone(),
+            fd,
+        })
+    }
+
+    pub fn wake(&self) -> io::Result<()> {
+        let buf = 1u64.to_ne_bytes();
+        match (&self.fd).write(&buf) {
+            Ok(_) => Ok(()),
+            // 计数器快要溢出了（u64::MAX - 1），先读出来清零再写一次
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.reset()?;
+                self.wake()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        let mut buf = [0; 8];
+        match (&self.fd).read(&mut buf) {
+            Ok(_) => Ok(()),
+            // 已经被其他线程清零了
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// epoll 在 fd 关闭时会自动移除注册，用户态保存注册信息的后端不会，关闭之前先 deregister,
+// 否则 io_uring 还没完成的 poll 请求会让 eventfd 一直留着，注册信息也要等 fd 号被复用时才会清掉
+impl Drop for Waker {
+    fn drop(&mut self) {
+        if let Err(e) = self.driver.delete(self.fd.as_raw_fd()) {
+            debug!("waker fd={} deregister failed: {}", self.fd.as_raw_fd(), e);
+        }
+    }
+}
+
+pub type Event = ffi::Event;
+impl Event {
+    pub fn id(&self) -> Token {
+        self.data()
+    }
+
+    // 套接字可读，或者对端关闭后可以读到 EOF
+    pub fn is_readable(&self) -> bool {
+        self.has(ffi::EPOLLIN)
+    }
+
+    // 套接字可写，或者非阻塞 connect 已经完成
+    pub fn is_writable(&self) -> bool {
+        self.has(ffi::EPOLLOUT)
+    }
+
+    // 套接字上有待处理的错误，比如收到 RST 或者 connect 失败，具体错误需要通过 SO_ERROR 获取
+    pub fn is_error(&self) -> bool {
+        self.has(ffi::EPOLLERR)
+    }
+
+    // 对端关闭了写方向（收到 FIN），读完缓冲区后会读到 EOF
+    // EPOLLHUP 表示两个方向都关闭了，所以同样视为读关闭
+    pub fn is_read_closed(&self) -> bool {
+        self.has(ffi::EPOLLRDHUP) || self.has(ffi::EPOLLHUP)
+    }
+
+    // 连接两个方向都已经关闭，不能再写入
+    pub fn is_write_closed(&self) -> bool {
+        self.has(ffi::EPOLLHUP)
+    }
+
+    // 有带外数据（TCP urgent data）可读
+    pub fn is_priority(&self) -> bool {
+        self.has(ffi::EPOLLPRI)
+    }
+
+    fn has(&self, flag: i32) -> bool {
+        self.events() & flag as u32 != 0
+    }
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Event")
+            .field("token", &self.id())
+            .field("events", &EventFlags(self.events()))
+            .finish()
+    }
+}
+
+// 把事件掩码解码成 `EPOLLIN | EPOLLRDHUP` 的形式方便调试
+struct EventFlags(u32);
+
+impl fmt::Debug for EventFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (ffi::EPOLLIN, "EPOLLIN"),
+            (ffi::EPOLLPRI, "EPOLLPRI"),
+            (ffi::EPOLLOUT, "EPOLLOUT"),
+            (ffi::EPOLLERR, "EPOLLERR"),
+            (ffi::EPOLLHUP, "EPOLLHUP"),
+            (ffi::EPOLLRDHUP, "EPOLLRDHUP"),
+        ];
+
+        let mut first = true;
+        for (flag, name) in flags {
+            if self.0 & flag as u32 != 0 {
+                if !first {
+                    f.write_str(" | ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        if first {
+            write!(f, "{:#x}", self.0)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct TcpStream {
+    // 必须在 inner 之前释放，从运行时里移除的时候 fd 还没有关闭
+    registration: Registration,
+    inner: net::TcpStream,
+}
+
+impl TcpStream {
+    // 非阻塞 connect: 创建非阻塞 socket 之后发起连接，内核返回 EINPROGRESS 时立即返回，不会等待三次握手
+    // 连接完成（成功或失败）时 socket 变为可写，注册 WRITABLE 兴趣等待事件，然后通过 take_error 判断是否连接成功
+    //
+    // NOTE: 地址解析仍然可能阻塞（DNS），并且只会尝试解析出来的第一个地址
+    pub fn connect(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        })?;
+
+        let domain = match addr {
+            net::SocketAddr::V4(..) => ffi::AF_INET,
+            net::SocketAddr::V6(..) => ffi::AF_INET6,
+        };
+        let fd = socket(
+            domain as i32,
+            ffi::SOCK_STREAM | ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC,
+            0,
+        )?;
+        // 交给标准库管理 fd, 连接失败返回时会被关闭
+        let stream = unsafe { net::TcpStream::from_raw_fd(fd) };
+
+        let (storage, len) = ffi::SockaddrStorage::from_socket_addr(&addr);
+        match connect(fd, &storage, len) {
+            Ok(()) => (),
+            Err(ref e) if e.raw_os_error() == Some(ffi::EINPROGRESS) => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(TcpStream::from_std(stream))
+    }
+
+    // 获取并清除 socket 上的错误（SO_ERROR）, 非阻塞 connect 完成之后用来判断握手是否成功
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn shutdown(&self, how: net::Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    // TCP_NODELAY: 关闭 Nagle 算法，小包立即发送，适合对延迟敏感的请求响应
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        self.inner.nodelay()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    // SO_KEEPALIVE: 连接空闲时由内核发送探测包，及时发现已经断开的对端
+    pub fn set_keepalive(&self, keepalive: bool) -> io::Result<()> {
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_KEEPALIVE,
+            keepalive as i32,
+        )
+    }
+
+    pub fn keepalive(&self) -> io::Result<bool> {
+        let keepalive: i32 = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_KEEPALIVE)?;
+        Ok(keepalive != 0)
+    }
+
+    // SO_LINGER: Some(timeout) 表示 close 时最多等待 timeout 把未发送的数据发完（秒级精度），
+    // Some(Duration::ZERO) 会直接发送 RST 丢弃未发送的数据，None 恢复默认行为
+    pub fn set_linger(&self, linger: Option<Duration>) -> io::Result<()> {
+        let linger = ffi::Linger {
+            l_onoff: linger.is_some() as i32,
+            // 超过 i32 范围的时长按最大值处理，直接转换会回绕成负数
+            l_linger: linger.map_or(0, |d| d.as_secs().min(i32::MAX as u64) as i32),
+        };
+        setsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_LINGER, linger)
+    }
+
+    pub fn linger(&self) -> io::Result<Option<Duration>> {
+        let linger: ffi::Linger = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_LINGER)?;
+        Ok((linger.l_onoff != 0).then(|| Duration::from_secs(linger.l_linger as u64)))
+    }
+
+    // SO_SNDBUF/SO_RCVBUF: 内核会对设置的值做调整（linux 上会翻倍用于存放元数据），以读取到的值为准
+    // 超过 i32 范围的大小按最大值处理，直接转换会回绕成 0 或者负数
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_SNDBUF,
+            i32::try_from(size).unwrap_or(i32::MAX),
+        )
+    }
+
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        let size: i32 = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_SNDBUF)?;
+        Ok(size as usize)
+    }
+
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_RCVBUF,
+            i32::try_from(size).unwrap_or(i32::MAX),
+        )
+    }
+
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        let size: i32 = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_RCVBUF)?;
+        Ok(size as usize)
+    }
+}
+
+impl TcpStream {
+    fn from_std(stream: net::TcpStream) -> TcpStream {
+        TcpStream {
+            registration: Registration::new(),
+            inner: stream,
+        }
+    }
+
+    pub(crate) fn as_std(&self) -> &net::TcpStream {
+        &self.inner
+    }
+
+    pub(crate) fn registration(&self) -> &Registration {
+        &self.registration
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // socket 始终保持非阻塞，数据读完之后返回 WouldBlock, 调用方需要等待下一次可读事件
+        (&self.inner).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        (&self.inner).read_vectored(bufs)
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl AsRawFd for TcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for TcpStream {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 TcpListener, 注册 READABLE 兴趣之后，有新连接到达时会收到可读事件
+pub struct TcpListener {
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    // bind 和 listen 交给标准库完成（会设置 SO_REUSEADDR），然后切换成非阻塞模式
+    pub fn bind(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
+        let listener = net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(TcpListener { inner: listener })
+    }
+
+    // 没有等待中的连接时返回 WouldBlock
+    // accept4 直接把新连接设置成非阻塞和 close-on-exec, 省掉一次额外的 fcntl 系统调用
+    pub fn accept(&self) -> io::Result<(TcpStream, net::SocketAddr)> {
+        let mut storage = ffi::SockaddrStorage::zeroed();
+        let mut len = std::mem::size_of::<ffi::SockaddrStorage>() as u32;
+        let fd = accept4(
+            self.inner.as_raw_fd(),
+            &mut storage,
+            &mut len,
+            ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC,
+        )?;
+        // 先交给标准库管理 fd, 地址解析失败时也能正确关闭
+        let stream = unsafe { net::TcpStream::from_raw_fd(fd) };
+        let addr = storage.to_socket_addr(len)?;
+
+        Ok((TcpStream::from_std(stream), addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for TcpListener {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 UdpSocket, 所有收发操作在没有数据或者发送缓冲区满时返回 WouldBlock
+// 可以同时注册 READABLE 和 WRITABLE 兴趣
+pub struct UdpSocket {
+    inner: net::UdpSocket,
+}
+
+impl UdpSocket {
+    pub fn bind(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
+        let socket = net::UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(UdpSocket { inner: socket })
+    }
+
+    // UDP 的 connect 只是设置默认的对端地址，不会阻塞，之后可以使用 send/recv
+    // 并且只会收到这个对端发来的数据报
+    pub fn connect(&self, addr: impl net::ToSocketAddrs) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    pub fn send_to(&self, buf: &[u8], target: impl net::ToSocketAddrs) -> io::Result<usize> {
+        self.inner.send_to(buf, target)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, net::SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    // 读取数据报但不从接收队列中移除
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, net::SocketAddr)> {
+        self.inner.peek_from(buf)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf)
+    }
+
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.peer_addr()
+    }
+}
+
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for UdpSocket {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 地址既可以是文件系统路径，也可以是 Linux 的抽象命名空间地址，
+// 抽象地址通过 `std::os::linux::net::SocketAddrExt::from_abstract_name` 创建后传给 `*_addr` 方法
+
+// 非阻塞的 UnixStream, 用法和 TcpStream 一样
+pub struct UnixStream {
+    inner: unix::UnixStream,
+}
+
+impl UnixStream {
+    // 非阻塞 connect: 和 TcpStream 一样先创建非阻塞 socket 再发起连接，不会阻塞事件循环
+    // 对端 backlog 没满时连接立即完成；backlog 满了内核返回 EAGAIN, 这时连接并没有在进行中，
+    // 直接把 WouldBlock 错误返回给调用者，由调用者稍后重试
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let (storage, len) =
+            ffi::SockaddrStorage::from_unix_path(path.as_ref().as_os_str().as_bytes(), false)?;
+        UnixStream::connect_raw(&storage, len)
+    }
+
+    pub fn connect_addr(addr: &unix::SocketAddr) -> io::Result<Self> {
+        let (storage, len) = if let Some(path) = addr.as_pathname() {
+            ffi::SockaddrStorage::from_unix_path(path.as_os_str().as_bytes(), false)?
+        } else if let Some(name) = addr.as_abstract_name() {
+            ffi::SockaddrStorage::from_unix_path(name, true)?
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot connect to an unnamed address",
+            ));
+        };
+        UnixStream::connect_raw(&storage, len)
+    }
+
+    fn connect_raw(addr: &ffi::SockaddrStorage, len: u32) -> io::Result<Self> {
+        let fd = socket(
+            ffi::AF_UNIX as i32,
+            ffi::SOCK_STREAM | ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC,
+            0,
+        )?;
+        // 交给标准库管理 fd, 连接失败返回时会被关闭
+        let stream = unsafe { unix::UnixStream::from_raw_fd(fd) };
+        match connect(fd, addr, len) {
+            Ok(()) => (),
+            Err(ref e) if e.raw_os_error() == Some(ffi::EINPROGRESS) => (),
+            Err(e) => return Err(e),
+        }
+        Ok(UnixStream { inner: stream })
+    }
+
+    // 通过 socketpair 创建一对互相连接的 socket, 常用于父子进程或者线程之间通信
+    pub fn pair() -> io::Result<(UnixStream, UnixStream)> {
+        let (a, b) = unix::UnixStream::pair()?;
+        Ok((UnixStream::from_std(a)?, UnixStream::from_std(b)?))
+    }
+
+    fn from_std(stream: unix::UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(UnixStream { inner: stream })
+    }
+
+    pub fn local_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn shutdown(&self, how: net::Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+}
+
+impl Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl AsRawFd for UnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for UnixStream {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 UnixListener, 有新连接时触发可读事件
+pub struct UnixListener {
+    inner: unix::UnixListener,
+}
+
+impl UnixListener {
+    // 路径已经存在时 bind 会失败，需要调用方先删除旧的 socket 文件
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        UnixListener::from_std(unix::UnixListener::bind(path)?)
+    }
+
+    pub fn bind_addr(addr: &unix::SocketAddr) -> io::Result<Self> {
+        UnixListener::from_std(unix::UnixListener::bind_addr(addr)?)
+    }
+
+    fn from_std(listener: unix::UnixListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(UnixListener { inner: listener })
+    }
+
+    // 没有等待中的连接时返回 WouldBlock
+    pub fn accept(&self) -> io::Result<(UnixStream, unix::SocketAddr)> {
+        let (stream, addr) = self.inner.accept()?;
+        Ok((UnixStream::from_std(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl AsRawFd for UnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for UnixListener {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 UnixDatagram
+pub struct UnixDatagram {
+    inner: unix::UnixDatagram,
+}
+
+impl UnixDatagram {
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        UnixDatagram::from_std(unix::UnixDatagram::bind(path)?)
+    }
+
+    pub fn bind_addr(addr: &unix::SocketAddr) -> io::Result<Self> {
+        UnixDatagram::from_std(unix::UnixDatagram::bind_addr(addr)?)
+    }
+
+    // 不绑定地址的 socket, 只能用来发送或者 connect 之后收发
+    pub fn unbound() -> io::Result<Self> {
+        UnixDatagram::from_std(unix::UnixDatagram::unbound()?)
+    }
+
+    pub fn pair() -> io::Result<(UnixDatagram, UnixDatagram)> {
+        let (a, b) = unix::UnixDatagram::pair()?;
+        Ok((UnixDatagram::from_std(a)?, UnixDatagram::from_std(b)?))
+    }
+
+    fn from_std(socket: unix::UnixDatagram) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(UnixDatagram { inner: socket })
+    }
+
+    pub fn connect(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.inner.connect(path)
+    }
+
+    pub fn connect_addr(&self, addr: &unix::SocketAddr) -> io::Result<()> {
+        self.inner.connect_addr(addr)
+    }
+
+    pub fn send_to(&self, buf: &[u8], path: impl AsRef<Path>) -> io::Result<usize> {
+        self.inner.send_to(buf, path)
+    }
+
+    pub fn send_to_addr(&self, buf: &[u8], addr: &unix::SocketAddr) -> io::Result<usize> {
+        self.inner.send_to_addr(buf, addr)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, unix::SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf)
+    }
+
+    pub fn local_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.peer_addr()
+    }
+}
+
+impl AsRawFd for UnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for UnixDatagram {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// Timer 使用的时钟
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clock {
+    // 系统挂起期间不计时，和 `std::time::Instant` 使用的是同一个时钟
+    Monotonic,
+    // 包括系统挂起的时间，适合"不管睡眠多久都要按时触发"的定时任务
+    Boottime,
+}
+
+// 第一次到期的时间
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expiration {
+    // 从现在开始经过多长时间
+    After(Duration),
+    // 时钟到达这个时刻，时刻通过 `Timer::now` 得到，已经过去的时刻会立即到期
+    At(Duration),
+}
+
+// 基于 timerfd 的定时器，和 socket 一样注册 READABLE 兴趣，到期之后收到可读事件
+// 读取时返回上次读取之后到期的次数，周期定时器处理不及时的时候次数会大于 1
+pub struct Timer {
+    fd: File,
+    clock: Clock,
+}
+
+impl Timer {
+    // 新建的定时器没有启动，需要调用 set 系列方法
+    pub fn new(clock: Clock) -> io::Result<Timer> {
+        let fd = timerfd_create(clock.id(), ffi::TFD_NONBLOCK | ffi::TFD_CLOEXEC)?;
+        let fd = unsafe { File::from_raw_fd(fd) };
+        debug!("timer fd={} clock={:?}", fd.as_raw_fd(), clock);
+        Ok(Timer { fd, clock })
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    // 定时器所用时钟的当前时刻，用来计算 `Expiration::At` 的绝对时间
+    pub fn now(&self) -> io::Result<Duration> {
+        clock_gettime(self.clock.id())
+    }
+
+    // 重新设置定时器，之前的设置和还没有读取的到期次数都会被清掉
+    // interval 为 None 时只触发一次，否则第一次到期之后按照 interval 周期触发
+    pub fn set(&self, expiration: Expiration, interval: Option<Duration>) -> io::Result<()> {
+        // it_interval 为 0 表示只触发一次，Some(0) 没有意义
+        if interval == Some(Duration::ZERO) {
             return Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Poll instance closed",
+                io::ErrorKind::InvalidInput,
+                "timer interval must be non-zero",
             ));
         }
-
-        // 获取stream socket的fd
-        let fd = stream.as_raw_fd();
-        // 注册socket可读事件
-        if interests.is_readable() {
-            // 然后注册对接收此套接字上 `Read` 事件通知的兴趣。`Event` 结构体用于指定要注册兴趣的事件以及其他使用标志的配置。
-            //
-            // `EPOLLIN` 表示对 `Read` 事件的兴趣。
-            // `EPOLLONESHOT` 表示在第一个事件之后从队列中移除所有兴趣。如果不这样做，我们需要在套接字处理完毕后手动 `deregister` 我们的兴趣。
-            //
-            // `epoll_data` 是用户提供的数据，因此我们可以在其中放置一个指针或整数值来标识事件。我们仅使用“i”即循环计数来识别事件。
-            let mut event = ffi::Event::new(ffi::EPOLLIN | ffi::EPOLLONESHOT, token);
-            epoll_ctl(self.epoll_fd, ffi::EPOLL_CTL_ADD, fd, &mut event)?;
+        let (flags, value) = match expiration {
+            Expiration::After(after) => (0, after),
+            Expiration::At(at) => (ffi::TFD_TIMER_ABSTIME, at),
+        };
+        // it_value 为 0 会停止定时器，这里让它尽快到期
+        let value = value.max(Duration::from_nanos(1));
+        let spec = ffi::Itimerspec {
+            it_interval: ffi::Timespec::from_duration(interval.unwrap_or(Duration::ZERO)),
+            it_value: ffi::Timespec::from_duration(value),
         };
+        trace!(
+            "timerfd_settime fd={} expiration={:?} interval={:?}",
+            self.fd.as_raw_fd(),
+            expiration,
+            interval
+        );
+        timerfd_settime(self.fd.as_raw_fd(), flags, &spec)
+    }
 
-        if interests.is_writable() {
-            unimplemented!();
-        }
+    // 经过 timeout 之后触发一次
+    pub fn set_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.set(Expiration::After(timeout), None)
+    }
 
-        Ok(())
+    // 每隔 interval 触发一次
+    pub fn set_interval(&self, interval: Duration) -> io::Result<()> {
+        self.set(Expiration::After(interval), Some(interval))
     }
 
-    // 将is_poll_dead设置为true之后，这里发送最后一个事件，关闭队列
-    pub fn close_loop(&self) -> io::Result<()> {
-        if self
-            .is_poll_dead
-            // .compare_and_swap(false, true, Ordering::SeqCst)
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_err()
-        {
-            return Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Poll instance closed",
-            ));
-        }
+    // 停止定时器，已经到期但还没有读取的次数也会被清掉
+    pub fn disarm(&self) -> io::Result<()> {
+        let spec = ffi::Itimerspec {
+            it_interval: ffi::Timespec::from_duration(Duration::ZERO),
+            it_value: ffi::Timespec::from_duration(Duration::ZERO),
+        };
+        timerfd_settime(self.fd.as_raw_fd(), 0, &spec)
+    }
 
-        // TODO: 优化这里，能达到目的，写法不好
-        let wake_fd = eventfd(1, 0)?;
-        let mut event = ffi::Event::new(ffi::EPOLLIN, 0);
-        epoll_ctl(self.epoll_fd, ffi::EPOLL_CTL_ADD, wake_fd, &mut event)?;
-        Ok(())
+    // 读出到期次数并清零，还没有到期时返回 WouldBlock
+    pub fn read(&self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        (&self.fd).read_exact(&mut buf)?;
+        Ok(u64::from_ne_bytes(buf))
     }
 }
 
-#[derive(Debug)]
-pub struct Selector {
-    epoll_fd: RawFd,
+impl Clock {
+    fn id(self) -> i32 {
+        match self {
+            Clock::Monotonic => ffi::CLOCK_MONOTONIC,
+            Clock::Boottime => ffi::CLOCK_BOOTTIME,
+        }
+    }
 }
 
-impl Selector {
-    pub fn new() -> io::Result<Self> {
-        Ok(Selector {
-            epoll_fd: epoll_create()?,
-        })
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timer")
+            .field("fd", &self.fd.as_raw_fd())
+            .field("clock", &self.clock)
+            .finish()
     }
+}
 
-    pub fn select(&self, events: &mut Events, timeout_ms: Option<i32>) -> io::Result<()> {
-        events.clear();
-        let timeout = timeout_ms.unwrap_or(-1);
-        epoll_wait(self.epoll_fd, events, 1024, timeout).map(|n_events| {
-            println!("finished event: {:?}", n_events);
-            unsafe { events.set_len(n_events as usize) };
-        })
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
     }
+}
 
-    pub fn registrator(&self, is_poll_dead: Arc<AtomicBool>) -> Registrator {
-        Registrator {
-            epoll_fd: self.epoll_fd,
-            is_poll_dead,
-        }
+impl Source for Timer {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
     }
 }
 
-impl Drop for Selector {
-    fn drop(&mut self) {
-        match close(self.epoll_fd) {
-            Ok(..) => (),
-            Err(e) => {
-                if !std::thread::panicking() {
-                    panic!("{}", e);
-                }
+// 基于 signalfd 的信号源，在事件循环里处理信号，不需要在信号处理函数里只调用异步信号安全的函数
+// 创建时在当前线程屏蔽这些信号，发给进程的信号只有在所有线程都屏蔽之后才一定会进入 signalfd,
+// 所以最好在创建其他线程之前创建（新线程会继承屏蔽字）。Drop 时不会解除屏蔽，否则还没读走的信号会按默认行为处理
+pub struct Signals {
+    fd: File,
+}
+
+impl Signals {
+    pub const SIGHUP: i32 = 1;
+    pub const SIGINT: i32 = 2;
+    pub const SIGQUIT: i32 = 3;
+    pub const SIGUSR1: i32 = 10;
+    pub const SIGUSR2: i32 = 12;
+    pub const SIGPIPE: i32 = 13;
+    pub const SIGALRM: i32 = 14;
+    pub const SIGTERM: i32 = 15;
+    pub const SIGCHLD: i32 = 17;
+    pub const SIGWINCH: i32 = 28;
+
+    pub fn new(signals: &[i32]) -> io::Result<Signals> {
+        let mut set = ffi::Sigset::empty();
+        for &signal in signals {
+            // 不存在的信号编号返回 EINVAL
+            if unsafe { ffi::sigaddset(&mut set, signal) } < 0 {
+                return Err(io::Error::last_os_error());
             }
         }
+        // pthread_sigmask 直接返回错误码，不设置 errno
+        let res = unsafe { ffi::pthread_sigmask(ffi::SIG_BLOCK, &set, ptr::null_mut()) };
+        if res != 0 {
+            return Err(io::Error::from_raw_os_error(res));
+        }
+
+        let fd = signalfd(&set, ffi::SFD_NONBLOCK | ffi::SFD_CLOEXEC)?;
+        let fd = unsafe { File::from_raw_fd(fd) };
+        debug!("signals fd={} signals={:?}", fd.as_raw_fd(), signals);
+        Ok(Signals { fd })
     }
-}
 
-pub type Event = ffi::Event;
-impl Event {
-    pub fn id(&self) -> Token {
-        self.data()
+    // 取出一个等待中的信号，没有信号时返回 WouldBlock
+    // 同一个信号在读取之前发送多次只会保留一个（实时信号除外）
+    pub fn read(&self) -> io::Result<SignalInfo> {
+        let mut info = ffi::SignalfdSiginfo::zeroed();
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(
+                &mut info as *mut ffi::SignalfdSiginfo as *mut u8,
+                mem::size_of::<ffi::SignalfdSiginfo>(),
+            )
+        };
+        (&self.fd).read_exact(buf)?;
+        Ok(SignalInfo {
+            signal: info.ssi_signo as i32,
+            code: info.ssi_code,
+            pid: info.ssi_pid,
+            uid: info.ssi_uid,
+            status: info.ssi_status,
+        })
     }
 }
 
-pub struct TcpStream {
-    inner: net::TcpStream,
+impl fmt::Debug for Signals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signals")
+            .field("fd", &self.fd.as_raw_fd())
+            .finish()
+    }
 }
 
-impl TcpStream {
-    pub fn connect(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
-        let stream = net::TcpStream::connect(addr)?;
-        stream.set_nonblocking(true)?;
+impl AsRawFd for Signals {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
 
-        Ok(TcpStream { inner: stream })
+impl Source for Signals {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
     }
 }
 
-impl Read for TcpStream {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.set_nonblocking(false)?;
-        (&self.inner).read(buf)
+// 从 signalfd 读出的一个信号
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignalInfo {
+    signal: i32,
+    code: i32,
+    pid: u32,
+    uid: u32,
+    status: i32,
+}
+
+impl SignalInfo {
+    pub fn signal(&self) -> i32 {
+        self.signal
     }
 
-    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
-        (&self.inner).read_vectored(bufs)
+    // si_code, 区分信号的来源，比如 kill 发送的是 SI_USER(0), SIGCHLD 的 CLD_EXITED(1)
+    pub fn code(&self) -> i32 {
+        self.code
     }
-}
 
-impl Write for TcpStream {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+    // 发送信号的进程，SIGCHLD 时是状态变化的子进程
+    pub fn pid(&self) -> u32 {
+        self.pid
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+    pub fn uid(&self) -> u32 {
+        self.uid
     }
-}
 
-impl AsRawFd for TcpStream {
-    fn as_raw_fd(&self) -> RawFd {
-        self.inner.as_raw_fd()
+    // SIGCHLD 时是子进程的退出码，或者导致子进程终止、停止的信号
+    pub fn status(&self) -> i32 {
+        self.status
     }
 }
 
 mod ffi {
+    use std::ffi::{c_long, c_void};
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+    use std::ptr;
+    use std::time::Duration;
 
     pub const EPOLL_CTL_ADD: i32 = 1;
-    #[allow(dead_code)]
     pub const EPOLL_CTL_DEL: i32 = 2;
+    pub const EPOLL_CTL_MOD: i32 = 3;
     pub const EPOLLIN: i32 = 0x1;
+    pub const EPOLLPRI: i32 = 0x2;
+    pub const EPOLLOUT: i32 = 0x4;
+    pub const EPOLLERR: i32 = 0x8;
+    pub const EPOLLHUP: i32 = 0x10;
+    pub const EPOLLRDHUP: i32 = 0x2000;
     pub const EPOLLONESHOT: i32 = 0x40000000;
+    pub const EPOLLET: i32 = 1 << 31;
+    pub const EFD_CLOEXEC: i32 = 0o2000000;
+    pub const EFD_NONBLOCK: i32 = 0o4000;
+    pub const CLOCK_MONOTONIC: i32 = 1;
+    pub const CLOCK_BOOTTIME: i32 = 7;
+    pub const TFD_CLOEXEC: i32 = 0o2000000;
+    pub const TFD_NONBLOCK: i32 = 0o4000;
+    pub const TFD_TIMER_ABSTIME: i32 = 1;
+    pub const SFD_CLOEXEC: i32 = 0o2000000;
+    pub const SFD_NONBLOCK: i32 = 0o4000;
+    pub const SIG_BLOCK: i32 = 0;
+
+    pub const AF_INET: u16 = 2;
+    pub const AF_INET6: u16 = 10;
+    pub const AF_UNIX: u16 = 1;
+    pub const SOCK_STREAM: i32 = 1;
+    pub const SOCK_NONBLOCK: i32 = 0o4000;
+    pub const SOCK_CLOEXEC: i32 = 0o2000000;
+    pub const EINPROGRESS: i32 = 115;
+    pub const EPERM: i32 = 1;
+    pub const ENOSYS: i32 = 38;
+    // 2020 年之后新增的系统调用在所有架构上使用统一的编号
+    pub const SYS_EPOLL_PWAIT2: i64 = 441;
+    #[cfg(target_arch = "x86_64")]
+    pub const SYS_KCMP: i64 = 312;
+    // aarch64、riscv64 等使用 asm-generic 编号的架构
+    #[cfg(not(target_arch = "x86_64"))]
+    pub const SYS_KCMP: i64 = 272;
+    pub const KCMP_FILE: i64 = 0;
+    pub const S_IFMT: u32 = 0o170000;
+    // 内核的 sigset_t 大小（_NSIG / 8）
+    pub const SIGSET_SIZE: usize = 8;
+    pub const SOL_SOCKET: i32 = 1;
+    pub const SO_SNDBUF: i32 = 7;
+    pub const SO_RCVBUF: i32 = 8;
+    pub const SO_KEEPALIVE: i32 = 9;
+    pub const SO_LINGER: i32 = 13;
 
     /// 由于同一名称多次使用，可能会造成混淆，但我们有一个 `Event` 结构体。
     /// 此结构体将文件描述符和一个名为 `events` 的字段绑定在一起。`events` 字段保存了哪些事件已准备好用于该文件描述符的信息。
@@ -187,6 +1337,231 @@ mod ffi {
         pub fn data(&self) -> usize {
             self.epoll_data
         }
+
+        pub fn events(&self) -> u32 {
+            self.events
+        }
+    }
+
+    /// 足够放下任何一种地址的 `struct sockaddr_storage`, 通过 `ss_family` 判断实际的地址类型
+    #[repr(C, align(8))]
+    pub struct SockaddrStorage {
+        pub ss_family: u16,
+        data: [u8; 126],
+    }
+
+    /// `struct sockaddr_in`, 端口和地址都是网络字节序
+    #[repr(C)]
+    pub struct SockaddrIn {
+        pub sin_family: u16,
+        pub sin_port: u16,
+        pub sin_addr: [u8; 4],
+        pub sin_zero: [u8; 8],
+    }
+
+    /// `struct sockaddr_in6`
+    #[repr(C)]
+    pub struct SockaddrIn6 {
+        pub sin6_family: u16,
+        pub sin6_port: u16,
+        pub sin6_flowinfo: u32,
+        pub sin6_addr: [u8; 16],
+        pub sin6_scope_id: u32,
+    }
+
+    /// `struct sockaddr_un`, 路径以 0 结尾，抽象地址以 0 开头
+    #[repr(C)]
+    pub struct SockaddrUn {
+        pub sun_family: u16,
+        pub sun_path: [u8; 108],
+    }
+
+    impl SockaddrStorage {
+        pub fn zeroed() -> Self {
+            SockaddrStorage {
+                ss_family: 0,
+                data: [0; 126],
+            }
+        }
+
+        // 把标准库的 SocketAddr 转换成内核需要的地址，同时返回地址的实际长度
+        pub fn from_socket_addr(addr: &SocketAddr) -> (Self, u32) {
+            let mut storage = SockaddrStorage::zeroed();
+            let len = match addr {
+                SocketAddr::V4(addr) => {
+                    let sockaddr = SockaddrIn {
+                        sin_family: AF_INET,
+                        sin_port: addr.port().to_be(),
+                        sin_addr: addr.ip().octets(),
+                        sin_zero: [0; 8],
+                    };
+                    unsafe { ptr::write(&mut storage as *mut Self as *mut SockaddrIn, sockaddr) };
+                    mem::size_of::<SockaddrIn>()
+                }
+                SocketAddr::V6(addr) => {
+                    let sockaddr = SockaddrIn6 {
+                        sin6_family: AF_INET6,
+                        sin6_port: addr.port().to_be(),
+                        sin6_flowinfo: addr.flowinfo().to_be(),
+                        sin6_addr: addr.ip().octets(),
+                        sin6_scope_id: addr.scope_id(),
+                    };
+                    unsafe { ptr::write(&mut storage as *mut Self as *mut SockaddrIn6, sockaddr) };
+                    mem::size_of::<SockaddrIn6>()
+                }
+            };
+            (storage, len as u32)
+        }
+
+        // 把 unix socket 的文件系统路径或者抽象名字转换成 `struct sockaddr_un`, 同时返回地址的实际长度
+        pub fn from_unix_path(path: &[u8], abstract_name: bool) -> io::Result<(Self, u32)> {
+            let mut sockaddr = SockaddrUn {
+                sun_family: AF_UNIX,
+                sun_path: [0; 108],
+            };
+            // 路径需要留一个字节放结尾的 0, 抽象名字需要留一个字节放开头的 0
+            if path.len() >= sockaddr.sun_path.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "path must be shorter than SUN_LEN",
+                ));
+            }
+            if !abstract_name && path.contains(&0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "paths must not contain interior null bytes",
+                ));
+            }
+            let start = abstract_name as usize;
+            sockaddr.sun_path[start..start + path.len()].copy_from_slice(path);
+
+            let mut storage = SockaddrStorage::zeroed();
+            unsafe { ptr::write(&mut storage as *mut Self as *mut SockaddrUn, sockaddr) };
+            // 抽象名字的长度就是名字本身，不包含结尾的 0
+            let len = mem::size_of::<u16>() + 1 + path.len();
+            Ok((storage, len as u32))
+        }
+
+        // 把内核填充的地址转换成标准库的 SocketAddr
+        pub fn to_socket_addr(&self, len: u32) -> io::Result<SocketAddr> {
+            let len = len as usize;
+            match self.ss_family {
+                AF_INET if len >= mem::size_of::<SockaddrIn>() => {
+                    let addr = unsafe { &*(self as *const Self as *const SockaddrIn) };
+                    Ok(SocketAddr::V4(SocketAddrV4::new(
+                        Ipv4Addr::from(addr.sin_addr),
+                        u16::from_be(addr.sin_port),
+                    )))
+                }
+                AF_INET6 if len >= mem::size_of::<SockaddrIn6>() => {
+                    let addr = unsafe { &*(self as *const Self as *const SockaddrIn6) };
+                    Ok(SocketAddr::V6(SocketAddrV6::new(
+                        Ipv6Addr::from(addr.sin6_addr),
+                        u16::from_be(addr.sin6_port),
+                        u32::from_be(addr.sin6_flowinfo),
+                        addr.sin6_scope_id,
+                    )))
+                }
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "unsupported address family",
+                )),
+            }
+        }
+    }
+
+    /// libc 的 `struct timespec`, time_t 和 long 一样大，在 32 位平台上只有 32 位
+    #[repr(C)]
+    pub struct Timespec {
+        pub tv_sec: c_long,
+        pub tv_nsec: c_long,
+    }
+
+    impl Timespec {
+        pub fn from_duration(duration: Duration) -> Self {
+            Timespec {
+                tv_sec: duration.as_secs().min(c_long::MAX as u64) as c_long,
+                tv_nsec: duration.subsec_nanos() as c_long,
+            }
+        }
+    }
+
+    /// `struct __kernel_timespec`, 在所有架构上都是两个 64 位整数，epoll_pwait2 和 io_uring 使用
+    #[repr(C)]
+    pub struct KernelTimespec {
+        pub tv_sec: i64,
+        pub tv_nsec: i64,
+    }
+
+    impl KernelTimespec {
+        pub fn from_duration(duration: Duration) -> Self {
+            KernelTimespec {
+                tv_sec: duration.as_secs().min(i64::MAX as u64) as i64,
+                tv_nsec: duration.subsec_nanos() as i64,
+            }
+        }
+    }
+
+    /// `struct itimerspec`, 第一次到期的时间和之后的周期
+    #[repr(C)]
+    pub struct Itimerspec {
+        pub it_interval: Timespec,
+        pub it_value: Timespec,
+    }
+
+    /// glibc 的 `sigset_t`, 比内核使用的 64 位大，全零就是空集合，通过 sigaddset 添加信号
+    #[repr(C)]
+    pub struct Sigset {
+        bits: [u64; 16],
+    }
+
+    impl Sigset {
+        pub fn empty() -> Self {
+            Sigset { bits: [0; 16] }
+        }
+    }
+
+    /// `struct signalfd_siginfo`, 固定 128 字节
+    #[repr(C)]
+    pub struct SignalfdSiginfo {
+        pub ssi_signo: u32,
+        pub ssi_errno: i32,
+        pub ssi_code: i32,
+        pub ssi_pid: u32,
+        pub ssi_uid: u32,
+        pub ssi_fd: i32,
+        pub ssi_tid: u32,
+        pub ssi_band: u32,
+        pub ssi_overrun: u32,
+        pub ssi_trapno: u32,
+        pub ssi_status: i32,
+        pub ssi_int: i32,
+        pub ssi_ptr: u64,
+        pub ssi_utime: u64,
+        pub ssi_stime: u64,
+        pub ssi_addr: u64,
+        pub ssi_addr_lsb: u16,
+        _pad2: u16,
+        pub ssi_syscall: i32,
+        pub ssi_call_addr: u64,
+        pub ssi_arch: u32,
+        _pad: [u8; 28],
+    }
+
+    const _: () = assert!(mem::size_of::<SignalfdSiginfo>() == 128);
+
+    impl SignalfdSiginfo {
+        pub fn zeroed() -> Self {
+            unsafe { mem::zeroed() }
+        }
+    }
+
+    /// `struct linger`, SO_LINGER 的参数
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    pub struct Linger {
+        pub l_onoff: i32,
+        pub l_linger: i32,
     }
 
     // linux系统调用
@@ -209,6 +1584,66 @@ mod ffi {
 
         /// http://man7.org/linux/man-pages/man2/timerfd_create.2.html
         pub fn eventfd(initva: u32, flags: i32) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/timerfd_create.2.html
+        pub fn timerfd_create(clockid: i32, flags: i32) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/timerfd_settime.2.html
+        pub fn timerfd_settime(
+            fd: i32,
+            flags: i32,
+            new_value: *const Itimerspec,
+            old_value: *mut Itimerspec,
+        ) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/clock_gettime.2.html
+        pub fn clock_gettime(clockid: i32, tp: *mut Timespec) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/signalfd.2.html
+        pub fn signalfd(fd: i32, mask: *const Sigset, flags: i32) -> i32;
+
+        /// http://man7.org/linux/man-pages/man3/sigaddset.3.html
+        pub fn sigaddset(set: *mut Sigset, signum: i32) -> i32;
+
+        /// http://man7.org/linux/man-pages/man3/pthread_sigmask.3.html
+        pub fn pthread_sigmask(how: i32, set: *const Sigset, oldset: *mut Sigset) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/syscall.2.html
+        ///
+        /// glibc 2.35 之前没有 epoll_pwait2 的封装，通过 syscall 直接调用
+        pub fn syscall(number: i64, ...) -> i64;
+
+        /// http://man7.org/linux/man-pages/man2/socket.2.html
+        pub fn socket(domain: i32, ty: i32, protocol: i32) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/connect.2.html
+        pub fn connect(sockfd: i32, addr: *const SockaddrStorage, addrlen: u32) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/setsockopt.2.html
+        pub fn setsockopt(
+            sockfd: i32,
+            level: i32,
+            optname: i32,
+            optval: *const c_void,
+            optlen: u32,
+        ) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/getsockopt.2.html
+        pub fn getsockopt(
+            sockfd: i32,
+            level: i32,
+            optname: i32,
+            optval: *mut c_void,
+            optlen: *mut u32,
+        ) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/accept4.2.html
+        pub fn accept4(
+            sockfd: i32,
+            addr: *mut SockaddrStorage,
+            addrlen: *mut u32,
+            flags: i32,
+        ) -> i32;
     }
 }
 
@@ -230,6 +1665,68 @@ fn close(fd: i32) -> io::Result<()> {
     }
 }
 
+// 注册时 fd 对应的文件，用户态保存注册信息的后端用它判断同一个 fd 号是不是还是注册时的那个文件
+#[derive(Debug)]
+enum FileId {
+    // 普通文件和 socket 比较设备号和 inode
+    Inode(u64, u64),
+    // eventfd、timerfd、signalfd 这类匿名 inode 共用同一个 inode, 只能复制一个 fd 留着，
+    // 之后用 kcmp 比较是不是同一个打开的文件。这些文件关闭时没有对外可见的行为，多留一会儿没有影响
+    Anon(OwnedFd),
+}
+
+impl FileId {
+    // 和 epoll 一样，无效的 fd 返回 EBADF
+    fn new(fd: RawFd) -> io::Result<FileId> {
+        // fd 不属于这里，不能关闭它
+        let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        let metadata = file.metadata()?;
+        if metadata.mode() & ffi::S_IFMT == 0 {
+            return Ok(FileId::Anon(file.as_fd().try_clone_to_owned()?));
+        }
+        Ok(FileId::Inode(metadata.dev(), metadata.ino()))
+    }
+
+    fn is_same(&self, other: &FileId) -> bool {
+        match (self, other) {
+            (FileId::Inode(dev, ino), FileId::Inode(other_dev, other_ino)) => {
+                (dev, ino) == (other_dev, other_ino)
+            }
+            (FileId::Anon(fd), FileId::Anon(other)) => {
+                match kcmp_file(fd.as_raw_fd(), other.as_raw_fd()) {
+                    Ok(same) => same,
+                    // kcmp 被 seccomp 拦截或者内核没有打开，区分不出来，保守地当成同一个文件
+                    Err(e) => {
+                        debug!("kcmp failed: {}", e);
+                        true
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+// 两个 fd 是不是指向同一个打开的文件
+fn kcmp_file(fd1: RawFd, fd2: RawFd) -> io::Result<bool> {
+    let pid = std::process::id() as i64;
+    let res = unsafe {
+        ffi::syscall(
+            ffi::SYS_KCMP,
+            pid,
+            pid,
+            ffi::KCMP_FILE,
+            fd1 as i64,
+            fd2 as i64,
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res == 0)
+    }
+}
+
 fn epoll_ctl(epfd: i32, op: i32, fd: i32, event: &mut Event) -> io::Result<()> {
     let res = unsafe { ffi::epoll_ctl(epfd, op, fd, event) };
     if res < 0 {
@@ -248,6 +1745,77 @@ fn epoll_wait(epfd: i32, events: &mut [Event], maxevents: i32, timeout: i32) ->
     }
 }
 
+// epoll_pwait2 的支持情况，第一次调用时探测，之后直接使用探测结果
+const PWAIT2_UNKNOWN: u8 = 0;
+const PWAIT2_SUPPORTED: u8 = 1;
+const PWAIT2_UNSUPPORTED: u8 = 2;
+static PWAIT2: AtomicU8 = AtomicU8::new(PWAIT2_UNKNOWN);
+
+// 有超时时间时优先使用 epoll_pwait2（linux 5.11+），支持纳秒精度的超时，
+// 内核不支持（ENOSYS）或者被 seccomp 拦截（EPERM）时退回 epoll_wait, 超时向上取整到毫秒，保证不会提前唤醒
+fn epoll_wait_timeout(
+    epfd: i32,
+    events: &mut [Event],
+    maxevents: i32,
+    timeout: Option<Duration>,
+) -> io::Result<i32> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return epoll_wait(epfd, events, maxevents, -1),
+    };
+
+    if PWAIT2.load(Ordering::Relaxed) != PWAIT2_UNSUPPORTED {
+        match epoll_pwait2(epfd, events, maxevents, timeout) {
+            Err(ref e)
+                if e.raw_os_error() == Some(ffi::ENOSYS)
+                    || e.raw_os_error() == Some(ffi::EPERM) =>
+            {
+                debug!(
+                    "epoll_pwait2 unavailable, falling back to epoll_wait: {}",
+                    e
+                );
+                PWAIT2.store(PWAIT2_UNSUPPORTED, Ordering::Relaxed);
+            }
+            res => {
+                PWAIT2.store(PWAIT2_SUPPORTED, Ordering::Relaxed);
+                return res;
+            }
+        }
+    }
+
+    epoll_wait(epfd, events, maxevents, timeout_to_millis(timeout))
+}
+
+// 向上取整到毫秒，超出 i32 的部分截断成 i32::MAX
+fn timeout_to_millis(timeout: Duration) -> i32 {
+    timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+}
+
+fn epoll_pwait2(
+    epfd: i32,
+    events: &mut [Event],
+    maxevents: i32,
+    timeout: Duration,
+) -> io::Result<i32> {
+    let timeout = ffi::KernelTimespec::from_duration(timeout);
+    let res = unsafe {
+        ffi::syscall(
+            ffi::SYS_EPOLL_PWAIT2,
+            epfd as i64,
+            events.as_mut_ptr(),
+            maxevents as i64,
+            &timeout as *const ffi::KernelTimespec,
+            ptr::null::<u8>(),
+            ffi::SIGSET_SIZE,
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res as i32)
+    }
+}
+
 fn eventfd(initva: u32, flags: i32) -> io::Result<i32> {
     let res = unsafe { ffi::eventfd(initva, flags) };
     if res < 0 {
@@ -256,3 +1824,101 @@ fn eventfd(initva: u32, flags: i32) -> io::Result<i32> {
         Ok(res)
     }
 }
+
+fn timerfd_create(clockid: i32, flags: i32) -> io::Result<i32> {
+    let res = unsafe { ffi::timerfd_create(clockid, flags) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn timerfd_settime(fd: i32, flags: i32, spec: &ffi::Itimerspec) -> io::Result<()> {
+    let res = unsafe { ffi::timerfd_settime(fd, flags, spec, ptr::null_mut()) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn signalfd(mask: &ffi::Sigset, flags: i32) -> io::Result<i32> {
+    let res = unsafe { ffi::signalfd(-1, mask, flags) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn clock_gettime(clockid: i32) -> io::Result<Duration> {
+    let mut tp = ffi::Timespec::from_duration(Duration::ZERO);
+    let res = unsafe { ffi::clock_gettime(clockid, &mut tp) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(Duration::new(tp.tv_sec as u64, tp.tv_nsec as u32))
+    }
+}
+
+fn accept4(
+    sockfd: i32,
+    addr: &mut ffi::SockaddrStorage,
+    addrlen: &mut u32,
+    flags: i32,
+) -> io::Result<i32> {
+    let res = unsafe { ffi::accept4(sockfd, addr, addrlen, flags) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn socket(domain: i32, ty: i32, protocol: i32) -> io::Result<i32> {
+    let res = unsafe { ffi::socket(domain, ty, protocol) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn connect(sockfd: i32, addr: &ffi::SockaddrStorage, addrlen: u32) -> io::Result<()> {
+    let res = unsafe { ffi::connect(sockfd, addr, addrlen) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn setsockopt<T>(fd: RawFd, level: i32, name: i32, value: T) -> io::Result<()> {
+    let res = unsafe {
+        ffi::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const T as *const c_void,
+            mem::size_of::<T>() as u32,
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn getsockopt<T: Copy>(fd: RawFd, level: i32, name: i32) -> io::Result<T> {
+    let mut value = mem::MaybeUninit::<T>::zeroed();
+    let mut len = mem::size_of::<T>() as u32;
+    let res =
+        unsafe { ffi::getsockopt(fd, level, name, value.as_mut_ptr() as *mut c_void, &mut len) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { value.assume_init() })
+    }
+}
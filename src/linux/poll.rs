@@ -0,0 +1,437 @@
+// poll(2) 后端，在 epoll 被 seccomp 拦截，或者某些字符设备在 epoll 下表现异常的时候使用
+//
+// 注册信息保存在用户态，每次 select 重新生成 pollfd 数组，三种触发模式这样模拟:
+//  - Level: 一直放在 pollfd 数组里，和 poll(2) 本身的语义一样
+//  - Oneshot: 事件交给用户之后不再放进数组，直到 reregister
+//  - Edge: 事件交给用户之后记下当时的状态，之后每次 select 先用零超时探测一次，
+//    状态变化（不再就绪、出现新的事件类型、可读的字节数变了）之后才重新放进数组。
+//    探测之后仍然没有变化的 fd 也留在数组里，只等待还没有就绪的事件类型
+//
+// 边沿触发的模拟并不完整:
+//  - 没有读到 WouldBlock 的 fd 在阻塞等待期间收到新数据不会唤醒 poll, 只有下一次 select 才能发现
+//  - 用户读到 WouldBlock 之后又收到了数据，探测时看到的还是可读，只能通过可读的字节数判断。
+//    字节数变少也当作新的边沿（只读了一部分时会多一次事件），但是恰好收到和之前一样多的字节时分辨不出来
+// 按照边沿触发的要求一直读写到 WouldBlock, 并且能处理多余的事件，就和 epoll 没有区别
+//
+// Waker 的 eventfd 比较特殊，计数器不会变少，这里在交出事件之后直接读出计数器清零，按水平触发处理
+
+use super::{epoll_events, eventfd, Event, FileId};
+use crate::{Interests, Token, Trigger};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub struct PollSet {
+    // 阻塞在 poll 上的时候注册信息发生了变化，通过它唤醒 poll 重新生成 pollfd 数组
+    notify: File,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    // 按注册顺序排列
+    fds: Vec<RawFd>,
+    registrations: HashMap<RawFd, Registration>,
+    // 下一次从这个位置开始取事件，就绪的 fd 比缓冲区多的时候每个 fd 都有机会被取到
+    cursor: usize,
+    // 是否有线程正阻塞在 poll 上
+    polling: bool,
+    seq: u64,
+}
+
+struct Registration {
+    token: Token,
+    interests: Interests,
+    // 注册时 fd 对应的文件，fd 没有 deregister 就关闭并且被复用的时候用来区分
+    file: FileId,
+    waker: bool,
+    state: State,
+    // 每次注册或修改都会变化，poll 期间被修改过的 fd 的结果直接丢掉
+    seq: u64,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    // 放进 pollfd 数组等待事件
+    Armed,
+    // oneshot 已经触发，直到 reregister 都不会再有事件
+    Disarmed,
+    // 边沿触发的事件已经交给用户，记录当时的就绪状态和可读的字节数
+    Fired { revents: i16, readable: Option<i32> },
+}
+
+impl PollSet {
+    pub fn new() -> io::Result<PollSet> {
+        let notify = eventfd(0, super::ffi::EFD_NONBLOCK | super::ffi::EFD_CLOEXEC)?;
+        let notify = unsafe { File::from_raw_fd(notify) };
+        debug!("poll notify fd={}", notify.as_raw_fd());
+        Ok(PollSet {
+            notify,
+            inner: Mutex::new(Inner {
+                fds: Vec::new(),
+                registrations: HashMap::new(),
+                cursor: 0,
+                polling: false,
+                seq: 0,
+            }),
+        })
+    }
+
+    pub fn add(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        self.insert(fd, token, interests, false)
+    }
+
+    // Waker 按水平触发注册，每次交出事件之后由这里清零计数器
+    pub fn add_waker(&self, fd: RawFd, token: Token) -> io::Result<()> {
+        self.insert(fd, token, Interests::READABLE | Interests::LEVEL, true)
+    }
+
+    pub fn modify(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        let mut inner = self.lock();
+        let seq = inner.next_seq();
+        match inner.registrations.get_mut(&fd) {
+            Some(registration) => {
+                registration.token = token;
+                registration.interests = interests;
+                registration.state = State::Armed;
+                registration.seq = seq;
+            }
+            None => return Err(io::Error::from_raw_os_error(ffi::ENOENT)),
+        }
+        self.notify(&inner)
+    }
+
+    pub fn delete(&self, fd: RawFd) -> io::Result<()> {
+        let mut inner = self.lock();
+        if !inner.remove(fd) {
+            return Err(io::Error::from_raw_os_error(ffi::ENOENT));
+        }
+        self.notify(&inner)
+    }
+
+    pub fn select(&self, events: &mut Vec<Event>, timeout: Option<Duration>) -> io::Result<()> {
+        events.clear();
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut timeout = timeout;
+        loop {
+            let (mut fds, seqs) = {
+                let mut inner = self.lock();
+                inner.refresh()?;
+                inner.polling = true;
+                inner.pollfds(self.notify.as_raw_fd())
+            };
+
+            trace!(
+                "ppoll nfds={} max_events={} timeout={:?}",
+                fds.len(),
+                events.capacity(),
+                timeout
+            );
+            let res = ppoll(&mut fds, timeout);
+            let mut inner = self.lock();
+            inner.polling = false;
+            let n = res?;
+            if fds[0].revents != 0 {
+                self.drain_notify()?;
+            }
+            inner.collect(&fds, &seqs, events);
+            trace!("ppoll got {} events", events.len());
+
+            // 超时，或者拿到了事件
+            if n == 0 || !events.is_empty() {
+                return Ok(());
+            }
+            // 只是注册信息发生了变化，重新生成 pollfd 数组，继续等待剩下的时间
+            if let Some(deadline) = deadline {
+                timeout = Some(deadline.saturating_duration_since(Instant::now()));
+            }
+        }
+    }
+
+    fn insert(&self, fd: RawFd, token: Token, interests: Interests, waker: bool) -> io::Result<()> {
+        let file = FileId::new(fd)?;
+        let mut inner = self.lock();
+        if let Some(registration) = inner.registrations.get(&fd) {
+            if registration.file.is_same(&file) {
+                return Err(io::Error::from_raw_os_error(ffi::EEXIST));
+            }
+            // 之前的文件没有 deregister 就关闭了，fd 号被新的文件复用，epoll 这时已经自动移除了旧的注册
+            debug!("poll fd={} was closed without deregister", fd);
+            inner.remove(fd);
+        }
+        let seq = inner.next_seq();
+        inner.registrations.insert(
+            fd,
+            Registration {
+                token,
+                interests,
+                file,
+                waker,
+                state: State::Armed,
+                seq,
+            },
+        );
+        inner.fds.push(fd);
+        self.notify(&inner)
+    }
+
+    fn notify(&self, inner: &Inner) -> io::Result<()> {
+        if !inner.polling {
+            return Ok(());
+        }
+        match (&self.notify).write(&1u64.to_ne_bytes()) {
+            Ok(_) => Ok(()),
+            // 计数器已经很大了，poll 肯定会被唤醒
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn drain_notify(&self) -> io::Result<()> {
+        match (&self.notify).read(&mut [0; 8]) {
+            Ok(_) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for PollSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollSet")
+            .field("notify", &self.notify.as_raw_fd())
+            .finish()
+    }
+}
+
+impl Inner {
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn remove(&mut self, fd: RawFd) -> bool {
+        if self.registrations.remove(&fd).is_none() {
+            return false;
+        }
+        self.fds.retain(|&registered| registered != fd);
+        true
+    }
+
+    // 探测已经触发过的边沿触发注册，状态变化了就重新放进 pollfd 数组
+    fn refresh(&mut self) -> io::Result<()> {
+        let mut fds: Vec<ffi::Pollfd> = self
+            .registrations
+            .iter()
+            .filter(|(_, registration)| matches!(registration.state, State::Fired { .. }))
+            .map(|(&fd, registration)| ffi::Pollfd::new(fd, registration.interests))
+            .collect();
+        if fds.is_empty() {
+            return Ok(());
+        }
+        ppoll(&mut fds, Some(Duration::ZERO))?;
+
+        for pollfd in &fds {
+            let registration = match self.registrations.get_mut(&pollfd.fd) {
+                Some(registration) => registration,
+                None => continue,
+            };
+            let (revents, readable) = match registration.state {
+                State::Fired { revents, readable } => (revents, readable),
+                _ => continue,
+            };
+            let now = fionread(pollfd.fd);
+            let changed = pollfd.revents == 0
+                || pollfd.revents & !revents != 0
+                || matches!((readable, now), (Some(before), Some(now)) if now != before);
+            registration.state = if changed {
+                State::Armed
+            } else {
+                // 消失的事件类型之后再出现也算新的边沿
+                State::Fired {
+                    revents: pollfd.revents,
+                    readable: now,
+                }
+            };
+        }
+        Ok(())
+    }
+
+    // 第一个位置固定是 notify, 同时返回每个 fd 注册时的序号
+    fn pollfds(&self, notify: RawFd) -> (Vec<ffi::Pollfd>, Vec<u64>) {
+        let mut fds = vec![ffi::Pollfd::new(notify, Interests::READABLE)];
+        let mut seqs = vec![0];
+        for fd in &self.fds {
+            let registration = &self.registrations[fd];
+            let pollfd = match registration.state {
+                State::Armed => ffi::Pollfd::new(*fd, registration.interests),
+                // 出错和挂断会一直就绪，不能再放进数组
+                State::Fired { revents, .. } if revents & (ffi::POLLERR | ffi::POLLHUP) == 0 => {
+                    let mut pollfd = ffi::Pollfd::new(*fd, registration.interests);
+                    pollfd.events &= !revents;
+                    pollfd
+                }
+                _ => continue,
+            };
+            fds.push(pollfd);
+            seqs.push(registration.seq);
+        }
+        (fds, seqs)
+    }
+
+    // 最多取出缓冲区容量那么多的事件
+    fn collect(&mut self, fds: &[ffi::Pollfd], seqs: &[u64], events: &mut Vec<Event>) {
+        let n = fds.len() - 1;
+        if n == 0 {
+            return;
+        }
+        let start = self.cursor % n;
+        for i in 0..n {
+            if events.len() == events.capacity() {
+                break;
+            }
+            let index = (start + i) % n + 1;
+            let pollfd = &fds[index];
+            if pollfd.revents == 0 {
+                continue;
+            }
+            let registration = match self.registrations.get_mut(&pollfd.fd) {
+                Some(registration) if registration.seq == seqs[index] => registration,
+                // poll 期间被 reregister 或者 deregister 了
+                _ => continue,
+            };
+            if pollfd.revents & ffi::POLLNVAL != 0 {
+                // fd 没有 deregister 就关闭了，和 epoll 一样移除这个注册，
+                // 继续放在数组里 poll 会一直立即返回，fd 号被复用之后也不能再注册
+                debug!("poll fd={} was closed without deregister", pollfd.fd);
+                self.remove(pollfd.fd);
+                continue;
+            }
+
+            // 已经触发过的边沿注册这次只等待新的事件类型，交给用户的是全部的就绪状态
+            let revents = match registration.state {
+                State::Fired { revents, .. } => pollfd.revents | revents,
+                _ => pollfd.revents,
+            };
+            events.push(Event::new(revents as u16 as i32, registration.token));
+            self.cursor = index;
+            if registration.waker {
+                drain(pollfd.fd);
+                continue;
+            }
+            registration.state = match registration.interests.trigger() {
+                Trigger::Level => State::Armed,
+                Trigger::Oneshot => State::Disarmed,
+                Trigger::Edge => State::Fired {
+                    revents,
+                    readable: fionread(pollfd.fd),
+                },
+            };
+        }
+    }
+}
+
+// 读出 Waker 的 eventfd 计数器
+fn drain(fd: RawFd) {
+    // fd 属于 Waker, 这里不能关闭它
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    let _ = file.read(&mut [0; 8]);
+}
+
+// 用零超时探测一组 fd 当前的就绪状态，io_uring 后端用它丢掉已经过期的边沿触发事件
+#[cfg(feature = "io-uring")]
+pub(super) fn probe(fds: &[(RawFd, Interests)]) -> io::Result<Vec<i16>> {
+    let mut pollfds: Vec<ffi::Pollfd> = fds
+        .iter()
+        .map(|&(fd, interests)| ffi::Pollfd::new(fd, interests))
+        .collect();
+    ppoll(&mut pollfds, Some(Duration::ZERO))?;
+    Ok(pollfds.iter().map(|pollfd| pollfd.revents).collect())
+}
+
+// 接收缓冲区里可以读的字节数，不支持 FIONREAD 的 fd 返回 None
+fn fionread(fd: RawFd) -> Option<i32> {
+    let mut n = 0;
+    let res = unsafe { ffi::ioctl(fd, ffi::FIONREAD, &mut n as *mut i32) };
+    if res < 0 {
+        None
+    } else {
+        Some(n)
+    }
+}
+
+fn ppoll(fds: &mut [ffi::Pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    let timeout = timeout.map(super::ffi::Timespec::from_duration);
+    let res = unsafe {
+        ffi::ppoll(
+            fds.as_mut_ptr(),
+            fds.len() as u64,
+            timeout.as_ref().map_or(ptr::null(), |timeout| {
+                timeout as *const super::ffi::Timespec
+            }),
+            ptr::null(),
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res as usize)
+    }
+}
+
+impl ffi::Pollfd {
+    fn new(fd: RawFd, interests: Interests) -> Self {
+        // poll 的事件位和 epoll 的取值一样，去掉触发模式相关的位
+        let events = epoll_events(interests) & !(super::ffi::EPOLLET | super::ffi::EPOLLONESHOT);
+        ffi::Pollfd {
+            fd,
+            events: events as i16,
+            revents: 0,
+        }
+    }
+}
+
+mod ffi {
+    use super::super::ffi::Timespec;
+    use std::ffi::c_void;
+
+    pub const POLLERR: i16 = 0x8;
+    pub const POLLHUP: i16 = 0x10;
+    pub const POLLNVAL: i16 = 0x20;
+    pub const FIONREAD: u64 = 0x541b;
+    pub const ENOENT: i32 = 2;
+    pub const EEXIST: i32 = 17;
+
+    /// `struct pollfd`
+    #[repr(C)]
+    pub struct Pollfd {
+        pub fd: i32,
+        pub events: i16,
+        pub revents: i16,
+    }
+
+    #[link(name = "c")]
+    extern "C" {
+        /// http://man7.org/linux/man-pages/man2/ppoll.2.html
+        ///
+        /// 和 poll 一样，只是超时可以精确到纳秒
+        pub fn ppoll(
+            fds: *mut Pollfd,
+            nfds: u64,
+            timeout: *const Timespec,
+            sigmask: *const c_void,
+        ) -> i32;
+
+        /// http://man7.org/linux/man-pages/man2/ioctl.2.html
+        pub fn ioctl(fd: i32, request: u64, ...) -> i32;
+    }
+}
@@ -0,0 +1,685 @@
+// io_uring 后端，通过 IORING_OP_POLL_ADD 获取就绪事件，对外的 Poll/Registrator/Event 和 epoll 后端完全一样
+//
+// io_uring 的 poll 请求完成一次就结束了，三种触发模式这样模拟:
+//  - Oneshot: 普通的 POLL_ADD, 完成之后直到 reregister 都不会再有事件
+//  - Edge: IORING_POLL_ADD_MULTI 多次触发的 poll, 内核默认按边沿触发处理。
+//    每次唤醒都会产生一个完成事件，同一个 fd 的多个完成事件合并成一个，
+//    交给用户之前再探测一次，用户已经读写到 WouldBlock 的过期事件直接丢掉（和 epoll 一样）
+//  - Level: 普通的 POLL_ADD, 事件交给用户之后在下一次 select 时重新提交，fd 仍然就绪时会立即完成
+//
+// 提交请求的 user_data 低 32 位是 fd, 高 32 位是每次提交递增的序号,
+// reregister/deregister 之前提交的请求产生的完成事件因为 user_data 对不上会被丢掉
+
+use super::{epoll_events, Event, FileId};
+use crate::{Interests, Token, Trigger};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// 提交队列的长度，完成队列默认是它的两倍，放不下的完成事件由内核暂存（IORING_FEAT_NODROP）
+const ENTRIES: u32 = 256;
+// POLL_REMOVE 请求本身的完成事件使用这个 user_data, 收到之后直接丢掉
+const REMOVE_DATA: u64 = u64::MAX;
+
+pub struct Ring {
+    fd: File,
+    inner: Mutex<Inner>,
+}
+
+// 提交队列、完成队列和注册信息，Registrator 可能在其他线程修改，所以都放在锁里
+struct Inner {
+    sq: SubmissionQueue,
+    cq: CompletionQueue,
+    registrations: HashMap<RawFd, Registration>,
+    // 上一次 select 交给用户的水平触发注册，下一次 select 时重新提交
+    rearm: Vec<(RawFd, u64)>,
+    seq: u32,
+    // 映射的内存必须比队列里的指针活得久
+    _maps: Vec<Mmap>,
+}
+
+// 队列里的指针只在持有锁的时候使用
+unsafe impl Send for Inner {}
+
+struct Registration {
+    token: Token,
+    interests: Interests,
+    // 注册时 fd 对应的文件，fd 没有 deregister 就关闭并且被复用的时候用来区分
+    file: FileId,
+    user_data: u64,
+}
+
+impl Ring {
+    pub fn new() -> io::Result<Ring> {
+        let mut params: ffi::Params = unsafe { mem::zeroed() };
+        let fd = io_uring_setup(ENTRIES, &mut params)?;
+        // 交给 File 管理 fd 的生命周期，后面的步骤失败时也会被关闭
+        let fd = unsafe { File::from_raw_fd(fd) };
+
+        // EXT_ARG（5.11）用来传超时时间，NODROP 保证完成事件不会丢，
+        // 多次触发的 poll 和 IORING_FEAT_RSRC_TAGS 同在 5.13 加入，内核没有单独的特性位，用后者来判断
+        let required =
+            ffi::IORING_FEAT_NODROP | ffi::IORING_FEAT_EXT_ARG | ffi::IORING_FEAT_RSRC_TAGS;
+        if params.features & required != required {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "io_uring is too old, linux 5.13+ is required",
+            ));
+        }
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * mem::size_of::<ffi::Cqe>();
+        let sqes_len = params.sq_entries as usize * mem::size_of::<ffi::Sqe>();
+
+        // 5.4 之后两个环形队列可以用一次 mmap 映射
+        let sq_ring = Mmap::new(&fd, sq_len.max(cq_len), ffi::IORING_OFF_SQ_RING)?;
+        let cq_ring = if params.features & ffi::IORING_FEAT_SINGLE_MMAP != 0 {
+            None
+        } else {
+            Some(Mmap::new(&fd, cq_len, ffi::IORING_OFF_CQ_RING)?)
+        };
+        let sqes = Mmap::new(&fd, sqes_len, ffi::IORING_OFF_SQES)?;
+
+        let sq = unsafe { SubmissionQueue::new(&sq_ring, &sqes, &params) };
+        let cq = unsafe { CompletionQueue::new(cq_ring.as_ref().unwrap_or(&sq_ring), &params) };
+        let mut maps = vec![sq_ring, sqes];
+        maps.extend(cq_ring);
+
+        debug!(
+            "io_uring fd={} sq_entries={} cq_entries={}",
+            fd.as_raw_fd(),
+            params.sq_entries,
+            params.cq_entries
+        );
+        Ok(Ring {
+            fd,
+            inner: Mutex::new(Inner {
+                sq,
+                cq,
+                registrations: HashMap::new(),
+                rearm: Vec::new(),
+                seq: 0,
+                _maps: maps,
+            }),
+        })
+    }
+
+    pub fn add(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        let file = FileId::new(fd)?;
+        let mut inner = self.lock();
+        if let Some(registration) = inner.registrations.get(&fd) {
+            if registration.file.is_same(&file) {
+                return Err(io::Error::from_raw_os_error(ffi::EEXIST));
+            }
+            // 之前的文件没有 deregister 就关闭了，fd 号被新的文件复用。
+            // 还没完成的 poll 请求持有旧文件的引用，取消之后旧文件才会真正关闭
+            debug!("io_uring fd={} was closed without deregister", fd);
+            let old = registration.user_data;
+            inner.push(self.raw_fd(), ffi::Sqe::poll_remove(old))?;
+        }
+        let user_data = inner.next_user_data(fd);
+        inner.registrations.insert(
+            fd,
+            Registration {
+                token,
+                interests,
+                file,
+                user_data,
+            },
+        );
+        inner.push(self.raw_fd(), ffi::Sqe::poll_add(fd, interests, user_data))?;
+        inner.submit(self.raw_fd())
+    }
+
+    // 先取消之前提交的 poll 请求，再按新的 token 和 interests 重新提交
+    pub fn modify(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        let mut inner = self.lock();
+        let old = match inner.registrations.get(&fd) {
+            Some(registration) => registration.user_data,
+            None => return Err(io::Error::from_raw_os_error(ffi::ENOENT)),
+        };
+        let user_data = inner.next_user_data(fd);
+        if let Some(registration) = inner.registrations.get_mut(&fd) {
+            registration.token = token;
+            registration.interests = interests;
+            registration.user_data = user_data;
+        }
+        inner.push(self.raw_fd(), ffi::Sqe::poll_remove(old))?;
+        inner.push(self.raw_fd(), ffi::Sqe::poll_add(fd, interests, user_data))?;
+        inner.submit(self.raw_fd())
+    }
+
+    pub fn delete(&self, fd: RawFd) -> io::Result<()> {
+        let mut inner = self.lock();
+        let registration = match inner.registrations.remove(&fd) {
+            Some(registration) => registration,
+            None => return Err(io::Error::from_raw_os_error(ffi::ENOENT)),
+        };
+        inner.push(self.raw_fd(), ffi::Sqe::poll_remove(registration.user_data))?;
+        inner.submit(self.raw_fd())
+    }
+
+    pub fn select(&self, events: &mut Vec<Event>, timeout: Option<Duration>) -> io::Result<()> {
+        events.clear();
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut timeout = timeout;
+        loop {
+            {
+                let mut inner = self.lock();
+                inner.rearm(self.raw_fd())?;
+            }
+
+            // 等待的时候不持有锁，其他线程仍然可以注册，新提交的请求完成时同样会唤醒这里
+            trace!(
+                "io_uring_enter fd={} max_events={} timeout={:?}",
+                self.raw_fd(),
+                events.capacity(),
+                timeout
+            );
+            let ts = timeout.map(super::ffi::KernelTimespec::from_duration);
+            let timed_out = match io_uring_enter(
+                self.raw_fd(),
+                0,
+                1,
+                ffi::IORING_ENTER_GETEVENTS,
+                ts.as_ref(),
+            ) {
+                Ok(_) => false,
+                Err(ref e) if e.raw_os_error() == Some(ffi::ETIME) => true,
+                Err(e) => return Err(e),
+            };
+
+            self.lock().reap(events);
+            trace!("io_uring fd={} got {} events", self.raw_fd(), events.len());
+
+            // 超时，或者拿到了事件
+            if timed_out || !events.is_empty() {
+                return Ok(());
+            }
+            // 完成的只是被取消的请求或者过期的结果，继续等待剩下的时间
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(());
+                }
+                timeout = Some(deadline - now);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for Ring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ring").field("fd", &self.raw_fd()).finish()
+    }
+}
+
+impl Inner {
+    fn next_user_data(&mut self, fd: RawFd) -> u64 {
+        self.seq = self.seq.wrapping_add(1);
+        (self.seq as u64) << 32 | fd as u32 as u64
+    }
+
+    // 提交队列满了就先提交给内核腾出位置
+    fn push(&mut self, ring_fd: RawFd, sqe: ffi::Sqe) -> io::Result<()> {
+        if self.sq.is_full() {
+            self.submit(ring_fd)?;
+        }
+        self.sq.push(sqe);
+        Ok(())
+    }
+
+    fn submit(&mut self, ring_fd: RawFd) -> io::Result<()> {
+        while self.sq.pending > 0 {
+            let n = io_uring_enter(ring_fd, self.sq.pending, 0, 0, None)?;
+            if n == 0 {
+                break;
+            }
+            self.sq.pending -= n.min(self.sq.pending);
+        }
+        Ok(())
+    }
+
+    // 重新提交水平触发的 poll, 期间被 reregister/deregister 过的跳过
+    fn rearm(&mut self, ring_fd: RawFd) -> io::Result<()> {
+        let rearm = mem::take(&mut self.rearm);
+        for (fd, user_data) in rearm {
+            let interests = match self.registrations.get(&fd) {
+                Some(registration) if registration.user_data == user_data => registration.interests,
+                _ => continue,
+            };
+            self.push(ring_fd, ffi::Sqe::poll_add(fd, interests, user_data))?;
+        }
+        self.submit(ring_fd)
+    }
+
+    // 最多取出缓冲区容量那么多的事件，剩下的留在完成队列里下次再取
+    fn reap(&mut self, events: &mut Vec<Event>) {
+        // 边沿触发的事件在 events 中的位置
+        let mut edges: HashMap<RawFd, usize> = HashMap::new();
+        while events.len() < events.capacity() {
+            let cqe = match self.cq.pop() {
+                Some(cqe) => cqe,
+                None => break,
+            };
+            if cqe.user_data == REMOVE_DATA {
+                continue;
+            }
+
+            let fd = cqe.user_data as u32 as RawFd;
+            let registration = match self.registrations.get(&fd) {
+                Some(registration) if registration.user_data == cqe.user_data => registration,
+                // 已经 reregister 或者 deregister 了
+                _ => continue,
+            };
+            if cqe.res < 0 {
+                // fd 无效（比如没有 deregister 就关闭了），这次注册不会再有事件，
+                // 和 epoll 一样移除它，fd 号被复用之后可以重新注册
+                debug!(
+                    "io_uring poll fd={} failed: {}",
+                    fd,
+                    io::Error::from_raw_os_error(-cqe.res)
+                );
+                self.registrations.remove(&fd);
+                edges.remove(&fd);
+                continue;
+            }
+
+            let rearm = match registration.interests.trigger() {
+                Trigger::Oneshot => false,
+                Trigger::Level => true,
+                // 多次触发的 poll 被内核结束了（比如完成队列溢出），重新提交
+                Trigger::Edge => cqe.flags & ffi::IORING_CQE_F_MORE == 0,
+            };
+            match edges.get(&fd) {
+                Some(&index) => {
+                    let readiness = events[index].events() as i32 | cqe.res;
+                    events[index] = Event::new(readiness, registration.token);
+                }
+                None => {
+                    if registration.interests.trigger() == Trigger::Edge {
+                        edges.insert(fd, events.len());
+                    }
+                    events.push(Event::new(cqe.res, registration.token));
+                }
+            }
+            if rearm {
+                self.rearm.push((fd, cqe.user_data));
+            }
+        }
+        if !edges.is_empty() {
+            self.drop_stale(events, edges);
+        }
+    }
+
+    // 只保留现在仍然就绪的事件类型，探测失败的话原样交给用户，最多是一次多余的事件
+    fn drop_stale(&self, events: &mut Vec<Event>, edges: HashMap<RawFd, usize>) {
+        let edges: Vec<(RawFd, usize)> = edges.into_iter().collect();
+        let fds: Vec<(RawFd, Interests)> = edges
+            .iter()
+            .map(|&(fd, _)| (fd, self.registrations[&fd].interests))
+            .collect();
+        let revents = match super::poll::probe(&fds) {
+            Ok(revents) => revents,
+            Err(e) => {
+                error!("io_uring probe edge events failed: {}", e);
+                return;
+            }
+        };
+
+        let mut stale = vec![false; events.len()];
+        for (&(_, index), revents) in edges.iter().zip(revents) {
+            let event = &mut events[index];
+            let readiness = event.events() as i32 & revents as u16 as i32;
+            if readiness == 0 {
+                stale[index] = true;
+            } else {
+                *event = Event::new(readiness, event.data());
+            }
+        }
+        let mut index = 0;
+        events.retain(|_| {
+            index += 1;
+            !stale[index - 1]
+        });
+    }
+}
+
+struct SubmissionQueue {
+    head: *const AtomicU32,
+    tail: *const AtomicU32,
+    mask: u32,
+    entries: u32,
+    sqes: *mut ffi::Sqe,
+    // 已经写入队列但还没有提交给内核的数量
+    pending: u32,
+}
+
+impl SubmissionQueue {
+    unsafe fn new(ring: &Mmap, sqes: &Mmap, params: &ffi::Params) -> Self {
+        let off = &params.sq_off;
+        let mask = *ring.at::<u32>(off.ring_mask);
+        let entries = *ring.at::<u32>(off.ring_entries);
+        // 提交队列通过 array 间接引用 sqes, 固定成一一对应
+        let array = ring.at::<u32>(off.array);
+        for i in 0..entries {
+            *array.add(i as usize) = i;
+        }
+        SubmissionQueue {
+            head: ring.at(off.head),
+            tail: ring.at(off.tail),
+            mask,
+            entries,
+            sqes: sqes.at(0),
+            pending: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        let head = unsafe { (*self.head).load(Ordering::Acquire) };
+        let tail = unsafe { (*self.tail).load(Ordering::Relaxed) };
+        tail.wrapping_sub(head) == self.entries
+    }
+
+    fn push(&mut self, sqe: ffi::Sqe) {
+        let tail = unsafe { (*self.tail).load(Ordering::Relaxed) };
+        unsafe {
+            ptr::write(self.sqes.add((tail & self.mask) as usize), sqe);
+            (*self.tail).store(tail.wrapping_add(1), Ordering::Release);
+        }
+        self.pending += 1;
+    }
+}
+
+struct CompletionQueue {
+    head: *const AtomicU32,
+    tail: *const AtomicU32,
+    mask: u32,
+    cqes: *const ffi::Cqe,
+}
+
+impl CompletionQueue {
+    unsafe fn new(ring: &Mmap, params: &ffi::Params) -> Self {
+        let off = &params.cq_off;
+        CompletionQueue {
+            head: ring.at(off.head),
+            tail: ring.at(off.tail),
+            mask: *ring.at::<u32>(off.ring_mask),
+            cqes: ring.at(off.cqes),
+        }
+    }
+
+    fn pop(&mut self) -> Option<ffi::Cqe> {
+        let head = unsafe { (*self.head).load(Ordering::Relaxed) };
+        let tail = unsafe { (*self.tail).load(Ordering::Acquire) };
+        if head == tail {
+            return None;
+        }
+        let cqe = unsafe { ptr::read(self.cqes.add((head & self.mask) as usize)) };
+        unsafe { (*self.head).store(head.wrapping_add(1), Ordering::Release) };
+        Some(cqe)
+    }
+}
+
+// 和内核共享的一段内存，drop 时解除映射
+struct Mmap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: &File, len: usize, offset: i64) -> io::Result<Mmap> {
+        let ptr = unsafe {
+            ffi::mmap(
+                ptr::null_mut(),
+                len,
+                ffi::PROT_READ | ffi::PROT_WRITE,
+                ffi::MAP_SHARED | ffi::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+        if ptr == ffi::MAP_FAILED {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Mmap {
+                ptr: ptr as *mut u8,
+                len,
+            })
+        }
+    }
+
+    fn at<T>(&self, offset: u32) -> *mut T {
+        unsafe { self.ptr.add(offset as usize) as *mut T }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { ffi::munmap(self.ptr as *mut _, self.len) };
+    }
+}
+
+impl ffi::Sqe {
+    fn poll_add(fd: RawFd, interests: Interests, user_data: u64) -> Self {
+        // 触发模式由 IORING_POLL_ADD_MULTI 决定，这里只需要读写相关的位
+        let events = epoll_events(interests) & !(super::ffi::EPOLLET | super::ffi::EPOLLONESHOT);
+        // 大端机器上内核会交换高低 16 位
+        #[cfg(target_endian = "big")]
+        let events = (events as u32).rotate_left(16) as i32;
+        let flags = match interests.trigger() {
+            Trigger::Edge => ffi::IORING_POLL_ADD_MULTI,
+            Trigger::Level | Trigger::Oneshot => 0,
+        };
+        ffi::Sqe {
+            opcode: ffi::IORING_OP_POLL_ADD,
+            fd,
+            len: flags,
+            op_flags: events as u32,
+            user_data,
+            ..Default::default()
+        }
+    }
+
+    fn poll_remove(target: u64) -> Self {
+        ffi::Sqe {
+            opcode: ffi::IORING_OP_POLL_REMOVE,
+            fd: -1,
+            addr: target,
+            user_data: REMOVE_DATA,
+            ..Default::default()
+        }
+    }
+}
+
+mod ffi {
+    use std::ffi::c_void;
+
+    pub const SYS_IO_URING_SETUP: i64 = 425;
+    pub const SYS_IO_URING_ENTER: i64 = 426;
+    pub const IORING_OFF_SQ_RING: i64 = 0;
+    pub const IORING_OFF_CQ_RING: i64 = 0x8000000;
+    pub const IORING_OFF_SQES: i64 = 0x10000000;
+    pub const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+    pub const IORING_FEAT_NODROP: u32 = 1 << 1;
+    pub const IORING_FEAT_EXT_ARG: u32 = 1 << 8;
+    pub const IORING_FEAT_RSRC_TAGS: u32 = 1 << 10;
+    pub const IORING_ENTER_GETEVENTS: u32 = 1 << 0;
+    pub const IORING_ENTER_EXT_ARG: u32 = 1 << 3;
+    pub const IORING_OP_POLL_ADD: u8 = 6;
+    pub const IORING_OP_POLL_REMOVE: u8 = 7;
+    pub const IORING_POLL_ADD_MULTI: u32 = 1 << 0;
+    pub const IORING_CQE_F_MORE: u32 = 1 << 1;
+    pub const PROT_READ: i32 = 0x1;
+    pub const PROT_WRITE: i32 = 0x2;
+    pub const MAP_SHARED: i32 = 0x1;
+    pub const MAP_POPULATE: i32 = 0x8000;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+    pub const ENOENT: i32 = 2;
+    pub const EEXIST: i32 = 17;
+    pub const ETIME: i32 = 62;
+
+    /// `struct io_uring_params`, 由 io_uring_setup 填充队列的大小和各个字段在映射内存中的偏移
+    #[repr(C)]
+    pub struct Params {
+        pub sq_entries: u32,
+        pub cq_entries: u32,
+        pub flags: u32,
+        pub sq_thread_cpu: u32,
+        pub sq_thread_idle: u32,
+        pub features: u32,
+        pub wq_fd: u32,
+        pub resv: [u32; 3],
+        pub sq_off: SqringOffsets,
+        pub cq_off: CqringOffsets,
+    }
+
+    /// `struct io_sqring_offsets`
+    #[repr(C)]
+    pub struct SqringOffsets {
+        pub head: u32,
+        pub tail: u32,
+        pub ring_mask: u32,
+        pub ring_entries: u32,
+        pub flags: u32,
+        pub dropped: u32,
+        pub array: u32,
+        pub resv1: u32,
+        pub user_addr: u64,
+    }
+
+    /// `struct io_cqring_offsets`
+    #[repr(C)]
+    pub struct CqringOffsets {
+        pub head: u32,
+        pub tail: u32,
+        pub ring_mask: u32,
+        pub ring_entries: u32,
+        pub overflow: u32,
+        pub cqes: u32,
+        pub flags: u32,
+        pub resv1: u32,
+        pub user_addr: u64,
+    }
+
+    /// `struct io_uring_sqe`, 只列出 poll 请求用到的字段，联合体按第一个成员命名
+    #[derive(Default)]
+    #[repr(C)]
+    pub struct Sqe {
+        pub opcode: u8,
+        pub flags: u8,
+        pub ioprio: u16,
+        pub fd: i32,
+        pub off: u64,
+        pub addr: u64,
+        pub len: u32,
+        pub op_flags: u32,
+        pub user_data: u64,
+        pub buf_index: u16,
+        pub personality: u16,
+        pub splice_fd_in: i32,
+        pub addr3: u64,
+        pub pad: u64,
+    }
+
+    /// `struct io_uring_cqe`, poll 请求的 res 是就绪的事件掩码，和 epoll 的取值一样
+    #[repr(C)]
+    pub struct Cqe {
+        pub user_data: u64,
+        pub res: i32,
+        pub flags: u32,
+    }
+
+    /// `struct io_uring_getevents_arg`, 配合 IORING_ENTER_EXT_ARG 传入超时时间
+    #[repr(C)]
+    pub struct GeteventsArg {
+        pub sigmask: u64,
+        pub sigmask_sz: u32,
+        pub pad: u32,
+        pub ts: u64,
+    }
+
+    #[link(name = "c")]
+    extern "C" {
+        /// http://man7.org/linux/man-pages/man2/syscall.2.html
+        ///
+        /// glibc 没有 io_uring 的封装，通过 syscall 直接调用
+        pub fn syscall(number: i64, ...) -> i64;
+
+        /// http://man7.org/linux/man-pages/man2/mmap.2.html
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: i64,
+        ) -> *mut c_void;
+
+        /// http://man7.org/linux/man-pages/man2/munmap.2.html
+        pub fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+}
+
+fn io_uring_setup(entries: u32, params: &mut ffi::Params) -> io::Result<RawFd> {
+    let res = unsafe {
+        ffi::syscall(
+            ffi::SYS_IO_URING_SETUP,
+            entries as i64,
+            params as *mut ffi::Params,
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res as RawFd)
+    }
+}
+
+// timeout 为 None 时一直等待，需要 IORING_FEAT_EXT_ARG
+fn io_uring_enter(
+    fd: RawFd,
+    to_submit: u32,
+    min_complete: u32,
+    flags: u32,
+    timeout: Option<&super::ffi::KernelTimespec>,
+) -> io::Result<u32> {
+    let arg = ffi::GeteventsArg {
+        sigmask: 0,
+        sigmask_sz: super::ffi::SIGSET_SIZE as u32,
+        pad: 0,
+        ts: timeout.map_or(0, |ts| ts as *const super::ffi::KernelTimespec as u64),
+    };
+    let res = unsafe {
+        ffi::syscall(
+            ffi::SYS_IO_URING_ENTER,
+            fd as i64,
+            to_submit as i64,
+            min_complete as i64,
+            (flags | ffi::IORING_ENTER_EXT_ARG) as i64,
+            &arg as *const ffi::GeteventsArg,
+            mem::size_of::<ffi::GeteventsArg>(),
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res as u32)
+    }
+}
@@ -1,15 +1,21 @@
-use crate::{Events, Interests, Token};
+use crate::runtime::Registration;
+use crate::{Events, Interests, Poll, Token, Trigger};
+use std::collections::HashMap;
+use std::ffi::c_void;
 use std::io::{IoSliceMut, Read, Write};
-use std::os::fd::{AsRawFd, RawFd};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net as unix;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::{io, net, ptr};
-
-pub type Source = std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{io, mem, net, ptr};
 
 // Registrator 这里我们在rust层面封装一个用于向macos注册感兴趣事件的注册器
 // 这里对应kqueue的注册调用，挂起等待结果响应在selector里实现
-// 他们两个应该持有同一个kqueue的fd
+// 他们两个通过 Arc 持有同一个kqueue的fd
 // 流程大致如下：
 //  1. 先使用Registrator注册我们感兴趣的事件
 //  2. 记录注册事件中的每个事件对应的 Token和后续处理的逻辑，如果是socket 读事件的话一般是一个stream的读取网络响应的逻辑
@@ -17,58 +23,143 @@ pub type Source = std::os::unix::io::RawFd;
 //  4. 一旦有响应之后将响应到event list中的kevent 对应的id 发送给记录token和后续处理函数的地方
 //  5. 根据返回的kevent 中的标识找到token对应的后续处理函数，可以继续执行了
 pub struct Registrator {
-    kq: Source,
+    kq: Arc<Kqueue>,
     is_poll_dead: Arc<AtomicBool>,
 }
 
 impl Registrator {
-    pub fn register(
+    pub fn register<S: crate::Source + ?Sized>(
         &self,
-        stream: &TcpStream,
-        token: usize,
+        source: &S,
+        token: Token,
         interests: Interests,
     ) -> io::Result<()> {
-        if self.is_poll_dead.load(Ordering::SeqCst) {
+        self.check_alive()?;
+        crate::check_token(token)?;
+
+        // 事件源的 fd
+        let fd = source.raw_fd();
+        // kqueue 对已经存在的 filter 再次 EV_ADD 就是修改，分不出重复注册，这里自己记录注册过的 fd
+        let file = file_id(fd)?;
+        let mut registered = self.kq.lock();
+        // 同一个 fd 号对应的已经是另一个文件时，说明之前的文件没有 deregister 就关闭了，
+        // 内核在关闭时已经删除了它的 knote, 可以直接注册
+        if registered.get(&fd) == Some(&file) {
             return Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Poll instance closed.",
+                io::ErrorKind::AlreadyExists,
+                "source is already registered",
             ));
         }
 
-        // 我们的socket 的 fd
-        let fd = stream.as_raw_fd();
-        // 如果是对socket 的读事件感兴趣就走这里
-        if interests.is_readable() {
-            // 使用工具函数创建一个读事件
-            let event = ffi::Event::new_read_event(fd, token as u64);
-            // 放入change list中
-            let event = [event];
-            // 进行注册
-            kevent(self.kq, &event, &mut [], 0, None)?;
-        };
+        // 进行注册
+        debug!(
+            "register kq={} fd={} token={} interests={:?}",
+            self.kq.fd, fd, token, interests
+        );
+        self.apply(fd, token, interests)?;
+        registered.insert(fd, file);
+        Ok(())
+    }
+
+    // oneshot 触发后 knote 已经被内核删除，重新 ADD 即可; 新的 interests 里没有的 filter 需要删除
+    pub fn reregister<S: crate::Source + ?Sized>(
+        &self,
+        source: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.check_alive()?;
+        crate::check_token(token)?;
+
+        let fd = source.raw_fd();
+        let file = file_id(fd)?;
+        let registered = self.kq.lock();
+        if registered.get(&fd) != Some(&file) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "source is not registered",
+            ));
+        }
+
+        debug!(
+            "reregister kq={} fd={} token={} interests={:?}",
+            self.kq.fd, fd, token, interests
+        );
+        self.apply(fd, token, interests)
+    }
 
-        // TODO: 如果是写事件走这里，待实现
-        if interests.is_writable() {
-            unimplemented!();
+    pub fn deregister<S: crate::Source + ?Sized>(&self, source: &S) -> io::Result<()> {
+        self.check_alive()?;
+
+        let fd = source.raw_fd();
+        let file = file_id(fd)?;
+        let mut registered = self.kq.lock();
+        if registered.get(&fd) != Some(&file) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "source is not registered",
+            ));
         }
 
+        debug!("deregister kq={} fd={}", self.kq.fd, fd);
+        // 读写两个 filter 都删除一次，oneshot 已经触发或者没有注册的 filter 返回 ENOENT
+        let changes = [
+            ffi::Event::new_delete_event(fd, ffi::EVFILT_READ),
+            ffi::Event::new_delete_event(fd, ffi::EVFILT_WRITE),
+        ];
+        submit(self.kq.fd, &changes)?;
+        registered.remove(&fd);
+        Ok(())
+    }
+
+    // 按 interests 添加需要的 filter, 删除不需要的 filter, 一次 kevent 调用提交
+    fn apply(&self, fd: RawFd, token: Token, interests: Interests) -> io::Result<()> {
+        // 触发模式: 水平触发不加标志，边沿触发对应 EV_CLEAR, 一次性触发对应 EV_ONESHOT
+        let trigger = match interests.trigger() {
+            Trigger::Level => 0,
+            Trigger::Edge => ffi::EV_CLEAR,
+            Trigger::Oneshot => ffi::EV_ONESHOT,
+        };
+        // kqueue 中读写是两个不同的 filter, 需要分别放入 change list
+        let changes = [
+            if interests.is_readable() {
+                ffi::Event::new_read_event(fd, token as u64, trigger | ffi::EV_RECEIPT)
+            } else {
+                ffi::Event::new_delete_event(fd, ffi::EVFILT_READ)
+            },
+            if interests.is_writable() {
+                ffi::Event::new_write_event(fd, token as u64, trigger | ffi::EV_RECEIPT)
+            } else {
+                ffi::Event::new_delete_event(fd, ffi::EVFILT_WRITE)
+            },
+        ];
+        submit(self.kq.fd, &changes)
+    }
+
+    // 对应的 Poll 是否已经通过 close_loop 关闭
+    pub fn is_closed(&self) -> bool {
+        self.is_poll_dead.load(Ordering::SeqCst)
+    }
+
+    fn check_alive(&self) -> io::Result<()> {
+        if self.is_closed() {
+            return Err(crate::closed());
+        }
         Ok(())
     }
 
     pub fn close_loop(&self) -> io::Result<()> {
         if self
             .is_poll_dead
-            .compare_and_swap(false, true, Ordering::SeqCst)
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
         {
-            return Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Poll instance closed.",
-            ));
+            return Err(crate::closed());
         }
 
         let event = ffi::Event::new_wakeup_event();
         let event = [event];
-        kevent(self.kq, &event, &mut [], 0, None)?;
+        kevent(self.kq.fd, &event, &mut [], 0, None)?;
 
         Ok(())
     }
@@ -84,33 +175,59 @@ impl Registrator {
 // 而进行后续处理的函数和对应的event token和后续处理调用都放在Executor中，Executor和Reactor通过channel通信
 #[derive(Debug)]
 pub struct Selector {
-    kq: Source,
+    kq: Arc<Kqueue>,
 }
 
 impl Selector {
     pub fn new() -> io::Result<Self> {
-        Ok(Selector { kq: kqueue()? })
+        Ok(Selector {
+            kq: Arc::new(Kqueue {
+                fd: kqueue()?,
+                registered: Mutex::new(HashMap::new()),
+            }),
+        })
     }
 
-    pub fn select(&self, events: &mut Events, timeout_ms: Option<i32>) -> io::Result<()> {
-        let n_events = events.capacity() as i32;
+    pub fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        let events = events.inner_mut();
         events.clear();
-        kevent(self.kq, &[], events, n_events, timeout_ms).map(|n_events| {
+        let n_events = events.capacity().min(i32::MAX as usize) as i32;
+        trace!("kevent kq={} max_events={}", self.kq.fd, n_events);
+        let res = kevent(self.kq.fd, &[], events, n_events, timeout).map(|n_events| {
+            trace!("kevent kq={} got {} events", self.kq.fd, n_events);
             unsafe { events.set_len(n_events as usize) };
-        })
+        });
+        // close_loop 的唤醒事件不交给用户
+        events.retain(|event| event.id() != crate::CLOSE_TOKEN);
+        res
     }
 
     pub fn registrator(&self, is_poll_dead: Arc<AtomicBool>) -> Registrator {
         Registrator {
-            kq: self.kq,
+            kq: self.kq.clone(),
             is_poll_dead,
         }
     }
 }
 
-impl Drop for Selector {
+// kqueue 的 fd, 最后一个持有者（Selector、Registrator 或者 Waker）释放时关闭,
+// 它们还在的时候 fd 号不会被其他文件复用
+#[derive(Debug)]
+struct Kqueue {
+    fd: RawFd,
+    // 注册过的 fd 和注册时对应的文件
+    registered: Mutex<HashMap<RawFd, FileId>>,
+}
+
+impl Kqueue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<RawFd, FileId>> {
+        self.registered.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Kqueue {
     fn drop(&mut self) {
-        match close(self.kq) {
+        match close(self.fd) {
             Ok(..) => (),
             Err(e) => {
                 if !std::thread::panicking() {
@@ -121,28 +238,246 @@ impl Drop for Selector {
     }
 }
 
+// Waker 用来从其他线程唤醒阻塞在 Poll::poll 上的线程，不会关闭 Poll, 可以重复调用
+// kqueue 自带用户事件 EVFILT_USER, 不需要额外的 fd, 用 token 作为 ident 区分不同的 Waker
+#[derive(Debug)]
+pub struct Waker {
+    kq: Arc<Kqueue>,
+    token: Token,
+}
+
+impl Waker {
+    pub fn new(poll: &Poll, token: Token) -> io::Result<Waker> {
+        let registrator = poll.registrator();
+        registrator.check_alive()?;
+        crate::check_token(token)?;
+
+        let event = [ffi::Event::new_user_event(token as u64, 0)];
+        kevent(registrator.kq.fd, &event, &mut [], 0, None)?;
+
+        Ok(Waker {
+            kq: registrator.kq.clone(),
+            token,
+        })
+    }
+
+    pub fn wake(&self) -> io::Result<()> {
+        let event = [ffi::Event::new_user_event(
+            self.token as u64,
+            ffi::NOTE_TRIGGER,
+        )];
+        kevent(self.kq.fd, &event, &mut [], 0, None)?;
+        Ok(())
+    }
+}
+
+// 用户事件不会随着某个 fd 关闭自动删除，需要手动从 kqueue 中删掉，
+// 否则同一个 token 重新创建 Waker 之前，旧的用户事件一直留在 kqueue 里
+impl Drop for Waker {
+    fn drop(&mut self) {
+        let event = [ffi::Event::new_user_delete_event(self.token as u64)];
+        if let Err(e) = submit(self.kq.fd, &event) {
+            debug!("waker token={} deregister failed: {}", self.token, e);
+        }
+    }
+}
+
 pub type Event = ffi::Event;
 impl Event {
     pub fn id(&self) -> Token {
         self.udata as usize
     }
+
+    // kqueue 每个 filter 单独返回一个事件，读写同时就绪时会收到两个相同 token 的事件
+    pub fn is_readable(&self) -> bool {
+        self.filter == ffi::EVFILT_READ
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.filter == ffi::EVFILT_WRITE
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.flags & ffi::EV_ERROR != 0
+    }
+
+    // kqueue 用 EV_EOF 表示对应方向已经关闭，读还是写由 filter 区分
+    pub fn is_read_closed(&self) -> bool {
+        self.filter == ffi::EVFILT_READ && self.flags & ffi::EV_EOF != 0
+    }
+
+    pub fn is_write_closed(&self) -> bool {
+        self.filter == ffi::EVFILT_WRITE && self.flags & ffi::EV_EOF != 0
+    }
+
+    // kqueue 没有单独的带外数据通知
+    pub fn is_priority(&self) -> bool {
+        false
+    }
 }
 
 pub struct TcpStream {
+    // 必须在 inner 之前释放，从运行时里移除的时候 fd 还没有关闭
+    registration: Registration,
     inner: net::TcpStream,
 }
 
 impl TcpStream {
+    // 非阻塞 connect, 和 linux 一样: 内核返回 EINPROGRESS 时立即返回，连接完成时 socket 变为可写，
+    // 再通过 take_error 判断是否连接成功
+    // macos 的 socket 不支持 SOCK_NONBLOCK / SOCK_CLOEXEC, 创建之后再用 fcntl 设置
+    //
+    // NOTE: 地址解析仍然可能阻塞（DNS），并且只会尝试解析出来的第一个地址
     pub fn connect(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
-        let stream = net::TcpStream::connect(addr)?;
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        })?;
+
+        let domain = match addr {
+            net::SocketAddr::V4(..) => ffi::AF_INET,
+            net::SocketAddr::V6(..) => ffi::AF_INET6,
+        };
+        let fd = socket(domain as i32, ffi::SOCK_STREAM, 0)?;
+        // 交给标准库管理 fd, 后面任何一步失败返回时都会被关闭
+        let stream = unsafe { net::TcpStream::from_raw_fd(fd) };
+        fcntl(fd, ffi::F_SETFD, ffi::FD_CLOEXEC)?;
         stream.set_nonblocking(true)?;
-        Ok(TcpStream { inner: stream })
+        // macos 没有 MSG_NOSIGNAL, 和标准库一样在 socket 上设置 SO_NOSIGPIPE, 对端关闭后写入返回 EPIPE 而不是发送信号
+        setsockopt(fd, ffi::SOL_SOCKET, ffi::SO_NOSIGPIPE, 1i32)?;
+
+        match connect(fd, &addr) {
+            Ok(()) => (),
+            Err(ref e) if e.raw_os_error() == Some(ffi::EINPROGRESS) => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(TcpStream::from_std(stream))
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn shutdown(&self, how: net::Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    // TCP_NODELAY: 关闭 Nagle 算法，小包立即发送，适合对延迟敏感的请求响应
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        self.inner.nodelay()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    // SO_KEEPALIVE: 连接空闲时由内核发送探测包，及时发现已经断开的对端
+    pub fn set_keepalive(&self, keepalive: bool) -> io::Result<()> {
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_KEEPALIVE,
+            keepalive as i32,
+        )
+    }
+
+    pub fn keepalive(&self) -> io::Result<bool> {
+        let keepalive: i32 = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_KEEPALIVE)?;
+        Ok(keepalive != 0)
+    }
+
+    // SO_LINGER: Some(timeout) 表示 close 时最多等待 timeout 把未发送的数据发完（秒级精度），
+    // Some(Duration::ZERO) 会直接发送 RST 丢弃未发送的数据，None 恢复默认行为
+    pub fn set_linger(&self, linger: Option<Duration>) -> io::Result<()> {
+        let linger = ffi::Linger {
+            l_onoff: linger.is_some() as i32,
+            // 超过 i32 范围的时长按最大值处理，直接转换会回绕成负数
+            l_linger: linger.map_or(0, |d| d.as_secs().min(i32::MAX as u64) as i32),
+        };
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_LINGER_SEC,
+            linger,
+        )
+    }
+
+    pub fn linger(&self) -> io::Result<Option<Duration>> {
+        let linger: ffi::Linger =
+            getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_LINGER_SEC)?;
+        Ok((linger.l_onoff != 0).then(|| Duration::from_secs(linger.l_linger as u64)))
+    }
+
+    // SO_SNDBUF/SO_RCVBUF: 内核可能对设置的值做调整，以读取到的值为准
+    // 超过 i32 范围的大小按最大值处理，直接转换会回绕成 0 或者负数
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_SNDBUF,
+            i32::try_from(size).unwrap_or(i32::MAX),
+        )
+    }
+
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        let size: i32 = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_SNDBUF)?;
+        Ok(size as usize)
+    }
+
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        setsockopt(
+            self.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_RCVBUF,
+            i32::try_from(size).unwrap_or(i32::MAX),
+        )
+    }
+
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        let size: i32 = getsockopt(self.as_raw_fd(), ffi::SOL_SOCKET, ffi::SO_RCVBUF)?;
+        Ok(size as usize)
+    }
+}
+
+impl TcpStream {
+    fn from_std(stream: net::TcpStream) -> TcpStream {
+        TcpStream {
+            registration: Registration::new(),
+            inner: stream,
+        }
+    }
+
+    pub(crate) fn as_std(&self) -> &net::TcpStream {
+        &self.inner
+    }
+
+    pub(crate) fn registration(&self) -> &Registration {
+        &self.registration
     }
 }
 
 impl Read for TcpStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.set_nonblocking(false)?;
+        // socket 始终保持非阻塞，数据读完之后返回 WouldBlock, 调用方需要等待下一次可读事件
         (&self.inner).read(buf)
     }
 
@@ -167,16 +502,398 @@ impl AsRawFd for TcpStream {
     }
 }
 
+impl crate::Source for TcpStream {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 TcpListener, 注册 READABLE 兴趣之后，有新连接到达时会收到可读事件
+pub struct TcpListener {
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    pub fn bind(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
+        let listener = net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(TcpListener { inner: listener })
+    }
+
+    // macos 没有 accept4, 新连接需要单独设置成非阻塞
+    pub fn accept(&self) -> io::Result<(TcpStream, net::SocketAddr)> {
+        let (stream, addr) = self.inner.accept()?;
+        stream.set_nonblocking(true)?;
+        Ok((TcpStream::from_std(stream), addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl crate::Source for TcpListener {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 UdpSocket, 所有收发操作在没有数据或者发送缓冲区满时返回 WouldBlock
+// 可以同时注册 READABLE 和 WRITABLE 兴趣
+pub struct UdpSocket {
+    inner: net::UdpSocket,
+}
+
+impl UdpSocket {
+    pub fn bind(addr: impl net::ToSocketAddrs) -> io::Result<Self> {
+        let socket = net::UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(UdpSocket { inner: socket })
+    }
+
+    // UDP 的 connect 只是设置默认的对端地址，不会阻塞，之后可以使用 send/recv
+    // 并且只会收到这个对端发来的数据报
+    pub fn connect(&self, addr: impl net::ToSocketAddrs) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    pub fn send_to(&self, buf: &[u8], target: impl net::ToSocketAddrs) -> io::Result<usize> {
+        self.inner.send_to(buf, target)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, net::SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    // 读取数据报但不从接收队列中移除
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, net::SocketAddr)> {
+        self.inner.peek_from(buf)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf)
+    }
+
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<net::SocketAddr> {
+        self.inner.peer_addr()
+    }
+}
+
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl crate::Source for UdpSocket {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 地址只能是文件系统路径（macos 没有抽象命名空间），`*_addr` 方法接收 `std::os::unix::net::SocketAddr`
+
+// 非阻塞的 UnixStream, 用法和 TcpStream 一样
+pub struct UnixStream {
+    inner: unix::UnixStream,
+}
+
+impl UnixStream {
+    // 非阻塞 connect, 和 linux 一样先创建非阻塞 socket 再发起连接，不会阻塞事件循环
+    // 对端 backlog 没满时连接立即完成，内核返回的其他错误（包括 backlog 满了）直接返回给调用者
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let fd = socket(ffi::AF_UNIX as i32, ffi::SOCK_STREAM, 0)?;
+        // 交给标准库管理 fd, 后面任何一步失败返回时都会被关闭
+        let stream = unsafe { unix::UnixStream::from_raw_fd(fd) };
+        fcntl(fd, ffi::F_SETFD, ffi::FD_CLOEXEC)?;
+        stream.set_nonblocking(true)?;
+        setsockopt(fd, ffi::SOL_SOCKET, ffi::SO_NOSIGPIPE, 1i32)?;
+
+        match connect_unix(fd, path.as_ref().as_os_str().as_bytes()) {
+            Ok(()) => (),
+            Err(ref e) if e.raw_os_error() == Some(ffi::EINPROGRESS) => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(UnixStream { inner: stream })
+    }
+
+    // macos 没有抽象命名空间，只能连接文件系统路径
+    pub fn connect_addr(addr: &unix::SocketAddr) -> io::Result<Self> {
+        match addr.as_pathname() {
+            Some(path) => UnixStream::connect(path),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot connect to an unnamed address",
+            )),
+        }
+    }
+
+    // 通过 socketpair 创建一对互相连接的 socket, 常用于父子进程或者线程之间通信
+    pub fn pair() -> io::Result<(UnixStream, UnixStream)> {
+        let (a, b) = unix::UnixStream::pair()?;
+        Ok((UnixStream::from_std(a)?, UnixStream::from_std(b)?))
+    }
+
+    fn from_std(stream: unix::UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(UnixStream { inner: stream })
+    }
+
+    pub fn local_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn shutdown(&self, how: net::Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+}
+
+impl Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl AsRawFd for UnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl crate::Source for UnixStream {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 UnixListener, 有新连接时触发可读事件
+pub struct UnixListener {
+    inner: unix::UnixListener,
+}
+
+impl UnixListener {
+    // 路径已经存在时 bind 会失败，需要调用方先删除旧的 socket 文件
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        UnixListener::from_std(unix::UnixListener::bind(path)?)
+    }
+
+    pub fn bind_addr(addr: &unix::SocketAddr) -> io::Result<Self> {
+        UnixListener::from_std(unix::UnixListener::bind_addr(addr)?)
+    }
+
+    fn from_std(listener: unix::UnixListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(UnixListener { inner: listener })
+    }
+
+    // 没有等待中的连接时返回 WouldBlock
+    pub fn accept(&self) -> io::Result<(UnixStream, unix::SocketAddr)> {
+        let (stream, addr) = self.inner.accept()?;
+        Ok((UnixStream::from_std(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl AsRawFd for UnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl crate::Source for UnixListener {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
+// 非阻塞的 UnixDatagram
+pub struct UnixDatagram {
+    inner: unix::UnixDatagram,
+}
+
+impl UnixDatagram {
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        UnixDatagram::from_std(unix::UnixDatagram::bind(path)?)
+    }
+
+    pub fn bind_addr(addr: &unix::SocketAddr) -> io::Result<Self> {
+        UnixDatagram::from_std(unix::UnixDatagram::bind_addr(addr)?)
+    }
+
+    // 不绑定地址的 socket, 只能用来发送或者 connect 之后收发
+    pub fn unbound() -> io::Result<Self> {
+        UnixDatagram::from_std(unix::UnixDatagram::unbound()?)
+    }
+
+    pub fn pair() -> io::Result<(UnixDatagram, UnixDatagram)> {
+        let (a, b) = unix::UnixDatagram::pair()?;
+        Ok((UnixDatagram::from_std(a)?, UnixDatagram::from_std(b)?))
+    }
+
+    fn from_std(socket: unix::UnixDatagram) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(UnixDatagram { inner: socket })
+    }
+
+    pub fn connect(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.inner.connect(path)
+    }
+
+    pub fn connect_addr(&self, addr: &unix::SocketAddr) -> io::Result<()> {
+        self.inner.connect_addr(addr)
+    }
+
+    pub fn send_to(&self, buf: &[u8], path: impl AsRef<Path>) -> io::Result<usize> {
+        self.inner.send_to(buf, path)
+    }
+
+    pub fn send_to_addr(&self, buf: &[u8], addr: &unix::SocketAddr) -> io::Result<usize> {
+        self.inner.send_to_addr(buf, addr)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, unix::SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf)
+    }
+
+    pub fn local_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<unix::SocketAddr> {
+        self.inner.peer_addr()
+    }
+}
+
+impl AsRawFd for UnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl crate::Source for UnixDatagram {
+    fn raw_fd(&self) -> RawFd {
+        self.as_raw_fd()
+    }
+}
+
 mod ffi {
     use super::*;
     use crate::Token;
 
     pub const EVFILT_READ: i16 = -1;
+    pub const EVFILT_WRITE: i16 = -2;
     pub const EVFILT_TIMER: i16 = -7;
+    pub const EVFILT_USER: i16 = -10;
+    pub const NOTE_TRIGGER: u32 = 0x01000000;
     pub const EV_ADD: u16 = 0x1;
+    pub const EV_DELETE: u16 = 0x2;
     pub const EV_ENABLE: u16 = 0x4;
     pub const EV_ONESHOT: u16 = 0x10;
     pub const EV_CLEAR: u16 = 0x20;
+    pub const EV_RECEIPT: u16 = 0x40;
+    pub const EV_ERROR: u16 = 0x4000;
+    pub const EV_EOF: u16 = 0x8000;
+    pub const ENOENT: i64 = 2;
+    pub const SOL_SOCKET: i32 = 0xffff;
+    pub const SO_KEEPALIVE: i32 = 0x0008;
+    pub const SO_SNDBUF: i32 = 0x1001;
+    pub const SO_RCVBUF: i32 = 0x1002;
+    pub const SO_NOSIGPIPE: i32 = 0x1022;
+    pub const AF_INET: u8 = 2;
+    pub const AF_INET6: u8 = 30;
+    pub const AF_UNIX: u8 = 1;
+    pub const SOCK_STREAM: i32 = 1;
+    pub const EINPROGRESS: i32 = 36;
+    pub const F_SETFD: i32 = 2;
+    pub const FD_CLOEXEC: i32 = 1;
+    // macos 的 SO_LINGER 单位是 tick, SO_LINGER_SEC 才是秒
+    pub const SO_LINGER_SEC: i32 = 0x1080;
+
+    /// `struct sockaddr_in`, BSD 的地址结构体开头多了一个长度字段，端口和地址都是网络字节序
+    #[repr(C)]
+    pub struct SockaddrIn {
+        pub sin_len: u8,
+        pub sin_family: u8,
+        pub sin_port: u16,
+        pub sin_addr: [u8; 4],
+        pub sin_zero: [u8; 8],
+    }
+
+    /// `struct sockaddr_in6`
+    #[repr(C)]
+    pub struct SockaddrIn6 {
+        pub sin6_len: u8,
+        pub sin6_family: u8,
+        pub sin6_port: u16,
+        pub sin6_flowinfo: u32,
+        pub sin6_addr: [u8; 16],
+        pub sin6_scope_id: u32,
+    }
+
+    /// `struct sockaddr_un`, 路径以 0 结尾
+    #[repr(C)]
+    pub struct SockaddrUn {
+        pub sun_len: u8,
+        pub sun_family: u8,
+        pub sun_path: [u8; 104],
+    }
+
+    /// `struct linger`, SO_LINGER_SEC 的参数
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    pub struct Linger {
+        pub l_onoff: i32,
+        pub l_linger: i32,
+    }
 
     // To be able to pass in a timeout to `Kqueue`we need to use
     // a timespec struct to pass in the information
@@ -191,31 +908,73 @@ mod ffi {
 
     impl Timespec {
         /// Convenience function so that we can easily create a `timespec` struct
-        /// from milliseconds. We won't support granularity smaller than ms
-        /// in our library even though we could on macos.
-        pub fn from_millis(milliseconds: i32) -> Self {
-            let seconds = milliseconds / 1000;
-            let nanoseconds = (milliseconds % 1000) * 1000 * 1000;
+        /// from a `Duration`, kqueue supports nanosecond granularity.
+        pub fn from_duration(duration: Duration) -> Self {
             Timespec {
-                tv_sec: seconds as isize,
-                v_nsec: nanoseconds as usize,
+                tv_sec: duration.as_secs().min(isize::MAX as u64) as isize,
+                v_nsec: duration.subsec_nanos() as usize,
             }
         }
     }
 
     pub type Event = Kevent;
     impl Event {
-        pub fn new_read_event(fd: RawFd, id: u64) -> Self {
+        pub fn new_read_event(fd: RawFd, id: u64, trigger: u16) -> Self {
             Event {
                 ident: fd as u64,
                 filter: EVFILT_READ,
-                flags: EV_ADD | EV_ENABLE | EV_ONESHOT,
+                flags: EV_ADD | EV_ENABLE | trigger,
                 fflags: 0,
                 data: 0,
                 udata: id,
             }
         }
 
+        pub fn new_write_event(fd: RawFd, id: u64, trigger: u16) -> Self {
+            Event {
+                ident: fd as u64,
+                filter: EVFILT_WRITE,
+                flags: EV_ADD | EV_ENABLE | trigger,
+                fflags: 0,
+                data: 0,
+                udata: id,
+            }
+        }
+
+        pub fn new_delete_event(fd: RawFd, filter: i16) -> Self {
+            Event {
+                ident: fd as u64,
+                filter,
+                flags: EV_DELETE | EV_RECEIPT,
+                fflags: 0,
+                data: 0,
+                udata: 0,
+            }
+        }
+
+        // EV_CLEAR 让用户事件在被取走之后自动复位，下一次 NOTE_TRIGGER 会再产生一个事件
+        pub fn new_user_event(id: u64, fflags: u32) -> Self {
+            Event {
+                ident: id,
+                filter: EVFILT_USER,
+                flags: EV_ADD | EV_ENABLE | EV_CLEAR,
+                fflags,
+                data: 0,
+                udata: id,
+            }
+        }
+
+        pub fn new_user_delete_event(id: u64) -> Self {
+            Event {
+                ident: id,
+                filter: EVFILT_USER,
+                flags: EV_DELETE | EV_RECEIPT,
+                fflags: 0,
+                data: 0,
+                udata: 0,
+            }
+        }
+
         pub fn new_wakeup_event() -> Self {
             Event {
                 ident: 0,
@@ -224,7 +983,8 @@ mod ffi {
                 fflags: 0,
                 // data is where our timeout will be set but we want to timeout immideately
                 data: 0,
-                udata: 0, // TODO: see if windows needs u32...
+                // Selector 按这个 token 把唤醒事件过滤掉，不交给用户
+                udata: crate::CLOSE_TOKEN as u64,
             }
         }
 
@@ -294,6 +1054,28 @@ mod ffi {
         ) -> i32;
 
         pub fn close(d: i32) -> i32;
+
+        pub fn socket(domain: i32, ty: i32, protocol: i32) -> i32;
+
+        pub fn connect(sockfd: i32, addr: *const c_void, addrlen: u32) -> i32;
+
+        pub fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+
+        pub fn setsockopt(
+            sockfd: i32,
+            level: i32,
+            optname: i32,
+            optval: *const c_void,
+            optlen: u32,
+        ) -> i32;
+
+        pub fn getsockopt(
+            sockfd: i32,
+            level: i32,
+            optname: i32,
+            optval: *mut c_void,
+            optlen: *mut u32,
+        ) -> i32;
     }
 }
 
@@ -315,13 +1097,13 @@ pub fn kevent(
     cl: &[ffi::Kevent],
     el: &mut [ffi::Kevent],
     n_events: i32,
-    timeout_ms: Option<i32>,
+    timeout: Option<Duration>,
 ) -> io::Result<usize> {
     let res = unsafe {
         let kq = kq as i32;
         let cl_len = cl.len() as i32;
 
-        let timeout = timeout_ms.map(ffi::Timespec::from_millis);
+        let timeout = timeout.map(ffi::Timespec::from_duration);
 
         let timeout: *const ffi::Timespec = match &timeout {
             Some(n) => n,
@@ -337,6 +1119,34 @@ pub fn kevent(
     Ok(res as usize)
 }
 
+// 提交带 EV_RECEIPT 的 change list, 每个 change 的结果写在对应 kevent 的 data 字段，
+// 而不是遇到第一个错误就整体返回。删除不存在的 filter 返回的 ENOENT 不算错误
+fn submit(kq: RawFd, changes: &[ffi::Kevent]) -> io::Result<()> {
+    let mut receipts = vec![ffi::Event::zero(); changes.len()];
+    let n = receipts.len() as i32;
+    kevent(kq, changes, &mut receipts, n, None)?;
+    for (change, receipt) in changes.iter().zip(&receipts) {
+        if receipt.flags & ffi::EV_ERROR == 0 || receipt.data == 0 {
+            continue;
+        }
+        if change.flags & ffi::EV_DELETE != 0 && receipt.data == ffi::ENOENT {
+            continue;
+        }
+        return Err(io::Error::from_raw_os_error(receipt.data as i32));
+    }
+    Ok(())
+}
+
+// 文件的标识（设备号和 inode）, 用来判断同一个 fd 号是不是还是注册时的那个文件
+type FileId = (u64, u64);
+
+fn file_id(fd: RawFd) -> io::Result<FileId> {
+    // fd 不属于这里，不能关闭它
+    let file = mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) });
+    let metadata = file.metadata()?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
 pub fn close(fd: RawFd) -> io::Result<()> {
     let res = unsafe { ffi::close(fd) };
     if res < 0 {
@@ -345,3 +1155,132 @@ pub fn close(fd: RawFd) -> io::Result<()> {
         Ok(())
     }
 }
+
+fn socket(domain: i32, ty: i32, protocol: i32) -> io::Result<i32> {
+    let res = unsafe { ffi::socket(domain, ty, protocol) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn fcntl(fd: RawFd, cmd: i32, arg: i32) -> io::Result<i32> {
+    let res = unsafe { ffi::fcntl(fd, cmd, arg) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+// 把标准库的 SocketAddr 转换成内核需要的地址结构体再发起连接
+fn connect(sockfd: RawFd, addr: &net::SocketAddr) -> io::Result<()> {
+    let res = match addr {
+        net::SocketAddr::V4(addr) => {
+            let sockaddr = ffi::SockaddrIn {
+                sin_len: mem::size_of::<ffi::SockaddrIn>() as u8,
+                sin_family: ffi::AF_INET,
+                sin_port: addr.port().to_be(),
+                sin_addr: addr.ip().octets(),
+                sin_zero: [0; 8],
+            };
+            unsafe {
+                ffi::connect(
+                    sockfd,
+                    &sockaddr as *const ffi::SockaddrIn as *const c_void,
+                    mem::size_of::<ffi::SockaddrIn>() as u32,
+                )
+            }
+        }
+        net::SocketAddr::V6(addr) => {
+            let sockaddr = ffi::SockaddrIn6 {
+                sin6_len: mem::size_of::<ffi::SockaddrIn6>() as u8,
+                sin6_family: ffi::AF_INET6,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo().to_be(),
+                sin6_addr: addr.ip().octets(),
+                sin6_scope_id: addr.scope_id(),
+            };
+            unsafe {
+                ffi::connect(
+                    sockfd,
+                    &sockaddr as *const ffi::SockaddrIn6 as *const c_void,
+                    mem::size_of::<ffi::SockaddrIn6>() as u32,
+                )
+            }
+        }
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+// 把 unix socket 的路径转换成 `struct sockaddr_un` 再发起连接
+fn connect_unix(sockfd: RawFd, path: &[u8]) -> io::Result<()> {
+    let mut sockaddr = ffi::SockaddrUn {
+        sun_len: 0,
+        sun_family: ffi::AF_UNIX,
+        sun_path: [0; 104],
+    };
+    // 需要留一个字节放结尾的 0
+    if path.len() >= sockaddr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path must be shorter than SUN_LEN",
+        ));
+    }
+    if path.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "paths must not contain interior null bytes",
+        ));
+    }
+    sockaddr.sun_path[..path.len()].copy_from_slice(path);
+    let len = 2 + path.len() + 1;
+    sockaddr.sun_len = len as u8;
+
+    let res = unsafe {
+        ffi::connect(
+            sockfd,
+            &sockaddr as *const ffi::SockaddrUn as *const c_void,
+            len as u32,
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn setsockopt<T>(fd: RawFd, level: i32, name: i32, value: T) -> io::Result<()> {
+    let res = unsafe {
+        ffi::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const T as *const c_void,
+            mem::size_of::<T>() as u32,
+        )
+    };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn getsockopt<T: Copy>(fd: RawFd, level: i32, name: i32) -> io::Result<T> {
+    let mut value = mem::MaybeUninit::<T>::zeroed();
+    let mut len = mem::size_of::<T>() as u32;
+    let res =
+        unsafe { ffi::getsockopt(fd, level, name, value.as_mut_ptr() as *mut c_void, &mut len) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { value.assume_init() })
+    }
+}
@@ -0,0 +1,29 @@
+// 内部日志宏：打开 `log` feature 时转发给 log crate, 否则不输出任何内容
+// 关闭时仍然通过 format_args! 引用参数，避免只在日志里用到的变量产生 unused 警告
+
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::trace!(target: "tinymio", $($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::debug!(target: "tinymio", $($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::error!(target: "tinymio", $($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    };
+}
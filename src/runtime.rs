@@ -0,0 +1,515 @@
+//! 单线程的 futures 运行时。
+//!
+//! 以前每个使用者都要自己写一个 Reactor 线程加上基于闭包的 Executor, 这里把它们合并到同一个线程里:
+//! `block_on` 在当前线程上轮询 future, 没有可以运行的任务时阻塞在 `Poll::poll` 上，
+//! 事件到达之后通过 token 找到等待它的 `std::task::Waker`, 唤醒对应的任务继续执行。
+//!
+//! ```no_run
+//! use tinymio::{runtime, TcpStream};
+//!
+//! runtime::block_on(async {
+//!     let stream = TcpStream::connect("127.0.0.1:9527").unwrap();
+//!     stream.write_all_async(b"ping").await.unwrap();
+//!     let mut buf = [0; 1024];
+//!     let n = stream.read_async(&mut buf).await.unwrap();
+//!     println!("{}", String::from_utf8_lossy(&buf[..n]));
+//! });
+//! ```
+
+use crate::{Events, Interests, Poll, Registrator, SourceFd, TcpStream, Token, TokenSlab};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{self, Future};
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::{self, Pin};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{self, Context, Wake};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+// 运行时保留的 token, TokenSlab 不会分配出这些值，互相之间以及和 close_loop 的 token 也都不同
+// WAKE_TOKEN 是其他线程唤醒任务时使用的 Waker, MAIN_TASK 是 block_on 传入的 future
+const WAKE_TOKEN: Token = crate::slab::reserved(1);
+const MAIN_TASK: Token = crate::slab::reserved(2);
+
+// 每个运行时的编号，从 1 开始，0 表示 IO 对象还没有注册到任何运行时
+static NEXT_RUNTIME_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Shared>>> = const { RefCell::new(None) };
+}
+
+/// 在当前线程上创建一个运行时并运行 `future` 直到完成。
+///
+/// 不能在另一个 `block_on` 里面调用。
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    Runtime::new()
+        .expect("failed to create tinymio runtime")
+        .block_on(future)
+}
+
+/// 在当前运行时里启动一个新任务，任务和 `block_on` 在同一个线程上执行，所以不要求 `Send`。
+///
+/// 返回的 `JoinHandle` 可以 `.await` 拿到任务的结果，丢弃它不会取消任务。
+/// `block_on` 返回时还没有完成的任务留在运行时里，同一个 `Runtime` 下一次 `block_on` 时继续执行，
+/// `Runtime` 释放时才会被丢弃。自由函数 `block_on` 每次都创建新的运行时，所以返回时就会丢弃它们。
+///
+/// # Panics
+///
+/// 不在运行时里调用时 panic。
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let shared = current().expect("spawn must be called from within a tinymio runtime");
+    let state = Rc::new(RefCell::new(JoinState {
+        output: None,
+        waker: None,
+    }));
+    let task_state = state.clone();
+    shared.spawn(Box::pin(async move {
+        let output = future.await;
+        let waker = {
+            let mut state = task_state.borrow_mut();
+            state.output = Some(output);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }));
+    JoinHandle { state }
+}
+
+pub struct Runtime {
+    poll: Poll,
+    events: Events,
+    shared: Rc<Shared>,
+}
+
+impl Runtime {
+    pub fn new() -> io::Result<Runtime> {
+        let poll = Poll::new()?;
+        let waker = crate::Waker::new(&poll, WAKE_TOKEN)?;
+        let shared = Shared {
+            id: NEXT_RUNTIME_ID.fetch_add(1, Ordering::Relaxed),
+            registrator: poll.registrator(),
+            sources: RefCell::new(TokenSlab::new()),
+            tasks: RefCell::new(TokenSlab::new()),
+            queue: Arc::new(RunQueue {
+                ready: Mutex::new(VecDeque::new()),
+                waker,
+                thread: thread::current().id(),
+            }),
+        };
+        Ok(Runtime {
+            poll,
+            events: Events::with_capacity(1024),
+            shared: Rc::new(shared),
+        })
+    }
+
+    /// 运行 `future` 直到完成，期间同时驱动 `spawn` 出来的任务。
+    pub fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let _enter = Enter::new(self.shared.clone());
+        let mut future = pin::pin!(future);
+        let main_waker = task::Waker::from(Arc::new(TaskWaker {
+            id: MAIN_TASK,
+            queue: self.shared.queue.clone(),
+        }));
+        self.shared.queue.schedule(MAIN_TASK);
+
+        loop {
+            // 只运行这一轮开始时已经就绪的任务，一直唤醒自己的任务不会饿死 IO 事件
+            let ready = self.shared.queue.len();
+            for _ in 0..ready {
+                let id = match self.shared.queue.pop() {
+                    Some(id) => id,
+                    None => break,
+                };
+                if id == MAIN_TASK {
+                    let mut cx = Context::from_waker(&main_waker);
+                    if let task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        return output;
+                    }
+                } else {
+                    self.shared.run_task(id);
+                }
+            }
+
+            // 还有就绪的任务时只检查一下事件，不阻塞
+            let timeout = if self.shared.queue.len() > 0 {
+                Some(Duration::ZERO)
+            } else {
+                None
+            };
+            self.poll
+                .poll(&mut self.events, timeout)
+                .expect("tinymio runtime poll failed");
+            for event in &self.events {
+                if event.id() != WAKE_TOKEN {
+                    self.shared.dispatch(event);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Runtime").field("poll", &self.poll).finish()
+    }
+}
+
+/// `spawn` 返回的句柄，`.await` 得到任务的结果。
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<task::Waker>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match state.output.take() {
+            Some(output) => task::Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                task::Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle").finish()
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+// block_on 期间保存在线程局部变量里，spawn 和 IO 类型通过它找到当前的运行时
+struct Shared {
+    // 不同运行时的 token 都从 0 开始分配，IO 对象记下注册它的运行时，避免操作别的运行时里同一个 token 的事件源
+    id: usize,
+    registrator: Registrator,
+    // reactor: token 对应的事件源和等待它的任务
+    sources: RefCell<TokenSlab<ScheduledIo>>,
+    tasks: RefCell<TokenSlab<Task>>,
+    queue: Arc<RunQueue>,
+}
+
+struct Task {
+    // 任务被轮询的时候先取出来，这样任务里可以继续 spawn
+    future: Option<BoxFuture>,
+    waker: task::Waker,
+}
+
+struct ScheduledIo {
+    fd: RawFd,
+    reader: Option<task::Waker>,
+    writer: Option<task::Waker>,
+}
+
+impl Shared {
+    fn spawn(&self, future: BoxFuture) {
+        let id = self.tasks.borrow_mut().insert_with(|id| Task {
+            future: Some(future),
+            waker: task::Waker::from(Arc::new(TaskWaker {
+                id,
+                queue: self.queue.clone(),
+            })),
+        });
+        self.queue.schedule(id);
+    }
+
+    fn run_task(&self, id: Token) {
+        let (mut future, waker) = match self.tasks.borrow_mut().get_mut(id) {
+            Some(task) => match task.future.take() {
+                Some(future) => (future, task.waker.clone()),
+                None => return,
+            },
+            // 已经完成的任务又被唤醒了
+            None => return,
+        };
+
+        let mut cx = Context::from_waker(&waker);
+        match future.as_mut().poll(&mut cx) {
+            task::Poll::Ready(()) => {
+                self.tasks.borrow_mut().remove(id);
+            }
+            task::Poll::Pending => {
+                if let Some(task) = self.tasks.borrow_mut().get_mut(id) {
+                    task.future = Some(future);
+                }
+            }
+        }
+        // 完成的 future 在这里释放，它持有的 IO 对象可能需要访问 sources
+    }
+
+    // 按事件类型唤醒等待读或者写的任务，出错和关闭时两边都唤醒，让它们从系统调用里拿到结果
+    fn dispatch(&self, event: &crate::Event) {
+        let (reader, writer) = match self.sources.borrow_mut().get_mut(event.id()) {
+            Some(io) => {
+                let readable = event.is_readable() || event.is_read_closed() || event.is_error();
+                let writable = event.is_writable() || event.is_write_closed() || event.is_error();
+                // 只唤醒就绪方向上的任务，另一个方向的 Waker 留着等下一次事件
+                (
+                    if readable { io.reader.take() } else { None },
+                    if writable { io.writer.take() } else { None },
+                )
+            }
+            None => return,
+        };
+        if let Some(waker) = reader {
+            waker.wake();
+        }
+        if let Some(waker) = writer {
+            waker.wake();
+        }
+    }
+
+    fn park(
+        &self,
+        registration: &Registration,
+        fd: RawFd,
+        interest: Interest,
+        waker: &task::Waker,
+    ) -> io::Result<()> {
+        let mut sources = self.sources.borrow_mut();
+        let mut token = registration.token.load(Ordering::Relaxed);
+        let registered = registration.runtime.load(Ordering::Relaxed) == self.id;
+        // 第一次等待，或者之前是在别的运行时里注册的
+        if !registered || !matches!(sources.get(token), Some(io) if io.fd == fd) {
+            token = sources.insert(ScheduledIo {
+                fd,
+                reader: None,
+                writer: None,
+            });
+            // 边沿触发只需要注册一次，任务总是在系统调用返回 WouldBlock 之后才等待，不会错过事件
+            let interests = Interests::READABLE | Interests::WRITABLE | Interests::EDGE;
+            if let Err(e) = self.registrator.register(&SourceFd(&fd), token, interests) {
+                sources.remove(token);
+                return Err(e);
+            }
+            registration.token.store(token, Ordering::Relaxed);
+            registration.runtime.store(self.id, Ordering::Relaxed);
+        }
+
+        let io = sources.get_mut(token).expect("source just registered");
+        let slot = match interest {
+            Interest::Read => &mut io.reader,
+            Interest::Write => &mut io.writer,
+        };
+        *slot = Some(waker.clone());
+        Ok(())
+    }
+}
+
+// 就绪的任务队列，其他线程上的 Waker 也会往里面放任务，所以需要 Arc + Mutex
+struct RunQueue {
+    ready: Mutex<VecDeque<Token>>,
+    // 从其他线程唤醒任务时，运行时可能正阻塞在 poll 上
+    waker: crate::Waker,
+    thread: ThreadId,
+}
+
+impl RunQueue {
+    fn schedule(&self, id: Token) {
+        {
+            let mut ready = self.ready.lock().unwrap_or_else(|e| e.into_inner());
+            if ready.contains(&id) {
+                return;
+            }
+            ready.push_back(id);
+        }
+        if thread::current().id() != self.thread {
+            if let Err(e) = self.waker.wake() {
+                error!("wake runtime failed: {}", e);
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<Token> {
+        self.ready
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.ready.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+struct TaskWaker {
+    id: Token,
+    queue: Arc<RunQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.schedule(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.schedule(self.id);
+    }
+}
+
+// 设置当前线程的运行时，离开 block_on 时清除
+struct Enter;
+
+impl Enter {
+    fn new(shared: Rc<Shared>) -> Enter {
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            assert!(
+                current.is_none(),
+                "cannot start a tinymio runtime from within a runtime"
+            );
+            *current = Some(shared);
+        });
+        Enter
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        let shared = CURRENT.with(|current| current.borrow_mut().take());
+        drop(shared);
+    }
+}
+
+fn current() -> Option<Rc<Shared>> {
+    CURRENT
+        .try_with(|current| current.borrow().clone())
+        .ok()
+        .flatten()
+}
+
+#[derive(Clone, Copy)]
+enum Interest {
+    Read,
+    Write,
+}
+
+/// IO 对象在运行时里的注册信息，第一次返回 WouldBlock 时才注册到当前线程的运行时，
+/// 所以同一个对象不用 `.await` 时仍然可以手动注册到自己的 `Poll` 上。
+#[derive(Debug)]
+pub(crate) struct Registration {
+    // 注册它的运行时的编号和在那个运行时里的 token
+    runtime: AtomicUsize,
+    token: AtomicUsize,
+}
+
+impl Registration {
+    pub(crate) fn new() -> Registration {
+        Registration {
+            runtime: AtomicUsize::new(0),
+            token: AtomicUsize::new(0),
+        }
+    }
+
+    // 先尝试系统调用，返回 WouldBlock 时把任务的 waker 登记到 reactor, 等事件到达之后再试
+    fn poll_io<R>(
+        &self,
+        fd: RawFd,
+        interest: Interest,
+        cx: &mut Context<'_>,
+        io: impl FnOnce() -> io::Result<R>,
+    ) -> task::Poll<io::Result<R>> {
+        match io() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            res => return task::Poll::Ready(res),
+        }
+
+        let shared = match current() {
+            Some(shared) => shared,
+            None => {
+                return task::Poll::Ready(Err(io::Error::other(
+                    "must be polled from within a tinymio runtime",
+                )))
+            }
+        };
+        match shared.park(self, fd, interest, cx.waker()) {
+            Ok(()) => task::Poll::Pending,
+            Err(e) => task::Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl Drop for Registration {
+    // IO 对象的 fd 还没有关闭，从 reactor 里移除；
+    // 在其他线程上，或者在别的运行时里释放时只能等注册它的运行时结束时回收
+    fn drop(&mut self) {
+        let runtime = *self.runtime.get_mut();
+        let token = *self.token.get_mut();
+        if let Some(shared) = current().filter(|shared| shared.id == runtime) {
+            let io = shared.sources.borrow_mut().remove(token);
+            if let Some(io) = io {
+                let _ = shared.registrator.deregister(&SourceFd(&io.fd));
+            }
+        }
+    }
+}
+
+impl TcpStream {
+    /// 尝试读取数据，socket 暂时没有数据时登记当前任务，可读之后唤醒它。
+    ///
+    /// 只能在 `runtime` 里调用，返回 `Poll::Ready(Ok(0))` 表示对端已经关闭。
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> task::Poll<io::Result<usize>> {
+        let fd = self.as_raw_fd();
+        self.registration()
+            .poll_io(fd, Interest::Read, cx, || self.as_std().read(buf))
+    }
+
+    /// 尝试写入数据，发送缓冲区满了（或者连接还没有建立）时登记当前任务，可写之后唤醒它。
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> task::Poll<io::Result<usize>> {
+        let fd = self.as_raw_fd();
+        self.registration()
+            .poll_io(fd, Interest::Write, cx, || self.as_std().write(buf))
+    }
+
+    // 和 `Read`/`Write` 的方法区分开，避免 `stream.read(..)` 调用到异步版本
+    pub async fn read_async(&self, buf: &mut [u8]) -> io::Result<usize> {
+        future::poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    pub async fn write_async(&self, buf: &[u8]) -> io::Result<usize> {
+        future::poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    pub async fn write_all_async(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_async(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// 一直读到对端关闭，返回读到的字节数。
+    pub async fn read_to_end_async(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0; 4096];
+        loop {
+            match self.read_async(&mut chunk).await? {
+                0 => return Ok(buf.len() - start),
+                n => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
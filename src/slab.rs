@@ -0,0 +1,158 @@
+use crate::Token;
+
+// Token 的低半部分是槽位下标，高半部分是这个槽位的代数（generation）
+// 槽位被释放之后代数加一，之前发出去的 Token 就再也匹配不上了，
+// 这样已经 deregister 的事件源迟到的事件不会被错误地派发给复用了同一个槽位的新事件源
+const INDEX_BITS: u32 = usize::BITS / 2;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+const GENERATION_MASK: usize = usize::MAX >> INDEX_BITS;
+
+/// 最大的槽位下标保留不用，下标部分全是 1 的 token 不管代数是多少都不会被分配出去，
+/// 可以放心地用作特殊的 token。`reserved(0)` 就是 `usize::MAX`, 参数不同得到的 token 也不同
+pub(crate) const fn reserved(n: usize) -> Token {
+    token(INDEX_MASK, GENERATION_MASK - n)
+}
+
+enum Slot<T> {
+    Occupied(T),
+    // 空闲槽位串成一个链表，指向下一个空闲槽位的下标
+    Vacant(usize),
+}
+
+struct Entry<T> {
+    generation: usize,
+    slot: Slot<T>,
+}
+
+/// 分配唯一的 `Token` 并保存每个事件源的状态，查找、插入和删除都是 O(1)。
+///
+/// 典型用法：`insert` 拿到 token 之后用它注册事件源，收到事件时用 `event.id()` 调用 `get_mut`
+/// 找到对应的状态，`deregister` 之后调用 `remove` 释放 token。
+/// 已经释放的 token 会被识别为过期 token, `get`/`get_mut`/`remove` 都返回 `None`。
+pub struct TokenSlab<T> {
+    entries: Vec<Entry<T>>,
+    // 空闲链表的头，等于 entries.len() 时表示没有空闲槽位
+    next_free: usize,
+    len: usize,
+}
+
+impl<T> TokenSlab<T> {
+    pub fn new() -> TokenSlab<T> {
+        TokenSlab::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> TokenSlab<T> {
+        TokenSlab {
+            entries: Vec::with_capacity(capacity),
+            next_free: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> Token {
+        self.insert_with(|_| value)
+    }
+
+    // 状态本身需要知道自己的 token 时使用，比如在回调里 reregister
+    pub fn insert_with(&mut self, f: impl FnOnce(Token) -> T) -> Token {
+        let index = self.next_free;
+        assert!(index < INDEX_MASK, "TokenSlab is full");
+
+        if index == self.entries.len() {
+            let token = token(index, 0);
+            self.entries.push(Entry {
+                generation: 0,
+                slot: Slot::Occupied(f(token)),
+            });
+            self.next_free = self.entries.len();
+            self.len += 1;
+            return token;
+        }
+
+        let entry = &mut self.entries[index];
+        let token = token(index, entry.generation);
+        match entry.slot {
+            Slot::Vacant(next_free) => self.next_free = next_free,
+            Slot::Occupied(..) => unreachable!("free list points to an occupied slot"),
+        }
+        entry.slot = Slot::Occupied(f(token));
+        self.len += 1;
+        token
+    }
+
+    pub fn contains(&self, token: Token) -> bool {
+        self.get(token).is_some()
+    }
+
+    pub fn get(&self, token: Token) -> Option<&T> {
+        let (index, generation) = split(token);
+        match self.entries.get(index) {
+            Some(Entry {
+                generation: g,
+                slot: Slot::Occupied(value),
+            }) if *g == generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, token: Token) -> Option<&mut T> {
+        let (index, generation) = split(token);
+        match self.entries.get_mut(index) {
+            Some(Entry {
+                generation: g,
+                slot: Slot::Occupied(value),
+            }) if *g == generation => Some(value),
+            _ => None,
+        }
+    }
+
+    // 释放 token, 槽位的代数加一，之后这个 token 就过期了
+    pub fn remove(&mut self, token: Token) -> Option<T> {
+        let (index, generation) = split(token);
+        let entry = self.entries.get_mut(index)?;
+        if entry.generation != generation || matches!(entry.slot, Slot::Vacant(..)) {
+            return None;
+        }
+
+        let slot = std::mem::replace(&mut entry.slot, Slot::Vacant(self.next_free));
+        entry.generation = (entry.generation + 1) & GENERATION_MASK;
+        self.next_free = index;
+        self.len -= 1;
+        match slot {
+            Slot::Occupied(value) => Some(value),
+            Slot::Vacant(..) => unreachable!(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Token, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| match &entry.slot {
+                Slot::Occupied(value) => Some((token(index, entry.generation), value)),
+                Slot::Vacant(..) => None,
+            })
+    }
+}
+
+impl<T> Default for TokenSlab<T> {
+    fn default() -> Self {
+        TokenSlab::new()
+    }
+}
+
+const fn token(index: usize, generation: usize) -> Token {
+    (generation << INDEX_BITS) | index
+}
+
+fn split(token: Token) -> (usize, usize) {
+    (token & INDEX_MASK, token >> INDEX_BITS)
+}
@@ -1,6 +1,7 @@
 use std::io::{Read, Write};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread::JoinHandle;
+use std::time::Duration;
 use std::{io, thread};
 /// 集成测试驱动开发
 /// 需求
@@ -36,6 +37,7 @@ fn proposed_api() {
     let mut executor = Excutor::new(evt_receiver);
 
     let mut stream = TcpStream::connect("127.0.0.1:9527").unwrap();
+    wait_connected(&stream);
     let request = format!(
         "GET /delay/{}/url/http://delay.com HTTP/1.1\r\n\
              Host: localhost\r\n\
@@ -52,7 +54,7 @@ fn proposed_api() {
     // 注册对stream感兴趣的(read)事件
     // 4. 注册事件
     registrator
-        .register(&mut stream, TEST_TOKEN, Interests::READABLE)
+        .register(&stream, TEST_TOKEN, Interests::READABLE)
         .expect("registration err.");
 
     // 把读取stream内容后续处理逻辑封装到函数里托管给executor
@@ -60,12 +62,22 @@ fn proposed_api() {
     // 这里就相当于挂起了我们等待response的后续处理逻辑，并没有在占用资源了
     // 但是真正的操作系统挂起是 epoll_wait, kqueue的监听事件响应的地方让出线程的
     // 这里是运行时层面的让出，好继续注册其他事件或者其他不需要阻塞持续执行的任务
+    let mut buffer = vec![];
     executor.suspend(TEST_TOKEN, move || {
-        let mut buffer = String::new();
-        stream.read_to_string(&mut buffer).unwrap();
+        // 读取是非阻塞的，响应还没有全部到达时返回 WouldBlock, 重新打开监听等待下一次事件
+        match stream.read_to_end(&mut buffer) {
+            Ok(..) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                registrator
+                    .reregister(&stream, TEST_TOKEN, Interests::READABLE)
+                    .expect("reregistration err.");
+                return;
+            }
+            Err(e) => panic!("Stream read err: {:?}, {}", e.kind(), e),
+        }
         registrator.close_loop().expect("close loop err.");
         assert!(!buffer.is_empty(), "Got an empty buffer");
-        println!("Got {}", buffer);
+        println!("Got {}", String::from_utf8_lossy(&buffer));
     });
 
     // executor开始监听之前注册的感兴趣的事件通知，收到通知后从自己托管的
@@ -73,6 +85,21 @@ fn proposed_api() {
     executor.block_on_all();
 }
 
+// connect 是非阻塞的，等连接建立（socket 可写）之后再发送请求
+fn wait_connected(stream: &TcpStream) {
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    registrator
+        .register(stream, 0, Interests::WRITABLE)
+        .expect("registration err.");
+    let mut events = Events::with_capacity(1);
+    poll.poll(&mut events, None).expect("poll err.");
+    if let Some(e) = stream.take_error().expect("take error err.") {
+        panic!("Connect err: {}", e);
+    }
+    registrator.deregister(stream).expect("deregistration err.");
+}
+
 struct Reactor {
     handle: Option<JoinHandle<()>>,
     registrator: Option<Registrator>,
@@ -87,9 +114,9 @@ impl Reactor {
             let mut events = Events::with_capacity(1024);
             loop {
                 // println!("Waiting! {:?}", poll);
-                match poll.poll(&mut events, Some(200)) {
+                match poll.poll(&mut events, Some(Duration::from_millis(200))) {
                     Ok(..) => (),
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => break,
+                    Err(ref e) if tinymio::is_closed(e) => break,
                     Err(e) => panic!("Poll error: {:?}, {}", e.kind(), e),
                 };
 
@@ -0,0 +1,283 @@
+#![cfg(target_os = "linux")]
+
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tinymio::{Backend, Clock, Events, Interests, Poll, Signals, SourceFd, Timer, Waker};
+
+const TOKEN: usize = 110;
+const WAKE_TOKEN: usize = 111;
+
+fn backends() -> Vec<Backend> {
+    let backends = vec![Backend::Epoll, Backend::Poll];
+    // 内核不支持 io_uring 时跳过
+    #[cfg(feature = "io-uring")]
+    let backends = {
+        let mut backends = backends;
+        if Poll::with_backend(Backend::IoUring).is_ok() {
+            backends.push(Backend::IoUring);
+        }
+        backends
+    };
+    backends
+}
+
+//  cargo test backend_semantics -- --nocapture
+#[test]
+fn backend_semantics() {
+    for backend in backends() {
+        println!("backend: {:?}", backend);
+        let mut poll = Poll::with_backend(backend).unwrap();
+        assert_eq!(backend, poll.backend());
+        let registrator = poll.registrator();
+        let mut events = Events::with_capacity(16);
+        let (mut reader, mut writer) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        let source = SourceFd(&reader.as_raw_fd());
+
+        // oneshot: 触发一次之后需要 reregister
+        writer.write_all(b"a").unwrap();
+        registrator
+            .register(&source, TOKEN, Interests::READABLE)
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(TOKEN, events[0].id());
+        assert!(events[0].is_readable());
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+
+        // 边沿触发：读到 WouldBlock 之后，新数据会产生新的事件
+        registrator
+            .reregister(&source, TOKEN, Interests::READABLE | Interests::EDGE)
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        let mut buf = [0; 8];
+        assert_eq!(1, reader.read(&mut buf).unwrap());
+        assert!(reader.read(&mut buf).is_err());
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+        writer.write_all(b"b").unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(TOKEN, events[0].id());
+
+        // 读到 WouldBlock 之后、下一次 poll 之前又收到了数据，即使比上次少也是新的边沿
+        writer.write_all(b"ccc").unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(4, reader.read(&mut buf).unwrap());
+        assert!(reader.read(&mut buf).is_err());
+        writer.write_all(b"dd").unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(TOKEN, events[0].id());
+        assert_eq!(2, reader.read(&mut buf).unwrap());
+
+        // 对端关闭
+        drop(writer);
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert!(events[0].is_read_closed());
+
+        registrator.deregister(&source).unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+    }
+}
+
+//  cargo test backend_cross_thread -- --nocapture
+#[test]
+fn backend_cross_thread() {
+    for backend in backends() {
+        println!("backend: {:?}", backend);
+        let mut poll = Poll::with_backend(backend).unwrap();
+        let waker = Arc::new(Waker::new(&poll, WAKE_TOKEN).unwrap());
+        let mut events = Events::with_capacity(16);
+
+        // 阻塞在 poll 上的时候，其他线程注册的事件源马上生效
+        let (reader, mut writer) = UnixStream::pair().unwrap();
+        writer.write_all(b"ready").unwrap();
+        let registrator = poll.registrator();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            registrator
+                .register(&SourceFd(&reader.as_raw_fd()), TOKEN, Interests::READABLE)
+                .unwrap();
+            reader
+        });
+        poll.poll(&mut events, None).unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(TOKEN, events[0].id());
+        let _reader = handle.join().unwrap();
+
+        // 边沿触发只报告过可写的事件源，阻塞等待期间变得可读也会被唤醒
+        let (mut reader, writer) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        let source = SourceFd(&reader.as_raw_fd());
+        let interests = Interests::READABLE | Interests::WRITABLE | Interests::EDGE;
+        poll.registrator()
+            .register(&source, TOKEN, interests)
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert!(events[0].is_writable());
+        assert!(!events[0].is_readable());
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            (&writer).write_all(b"ready").unwrap();
+            writer
+        });
+        poll.poll(&mut events, None).unwrap();
+        assert_eq!(1, events.len());
+        assert!(events[0].is_readable());
+        let _writer = handle.join().unwrap();
+        reader.read_exact(&mut [0; 5]).unwrap();
+        poll.registrator().deregister(&source).unwrap();
+
+        // Waker 可以重复唤醒
+        for _ in 0..2 {
+            let waker = waker.clone();
+            let handle = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                waker.wake().unwrap();
+            });
+            poll.poll(&mut events, None).unwrap();
+            assert_eq!(1, events.len());
+            assert_eq!(WAKE_TOKEN, events[0].id());
+            handle.join().unwrap();
+        }
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+    }
+}
+
+extern "C" {
+    fn dup2(oldfd: i32, newfd: i32) -> i32;
+}
+
+//  cargo test backend_fd_reuse -- --nocapture
+#[test]
+fn backend_fd_reuse() {
+    for backend in backends() {
+        println!("backend: {:?}", backend);
+        let mut poll = Poll::with_backend(backend).unwrap();
+        let registrator = poll.registrator();
+        let mut events = Events::with_capacity(16);
+
+        // 注册之后没有 deregister 就关闭，fd 号被另一个 socket 复用
+        // dup2 原子地关闭旧的文件并复用 fd 号，不会被其他线程抢走
+        let (old, _old_peer) = UnixStream::pair().unwrap();
+        let fd = old.into_raw_fd();
+        registrator
+            .register(
+                &SourceFd(&fd),
+                TOKEN,
+                Interests::READABLE | Interests::LEVEL,
+            )
+            .unwrap();
+        let (new, mut new_peer) = UnixStream::pair().unwrap();
+        assert_eq!(fd, unsafe { dup2(new.as_raw_fd(), fd) });
+        let reused = unsafe { UnixStream::from_raw_fd(fd) };
+        drop(new);
+
+        // 和 epoll 一样可以直接注册复用了 fd 号的新文件，事件照常送达
+        registrator
+            .register(&SourceFd(&fd), TOKEN + 1, Interests::READABLE)
+            .unwrap();
+        new_peer.write_all(b"a").unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(TOKEN + 1, events[0].id());
+        assert_eq!(
+            io::ErrorKind::AlreadyExists,
+            registrator
+                .register(&SourceFd(&fd), TOKEN, Interests::READABLE)
+                .unwrap_err()
+                .kind()
+        );
+
+        // 水平触发的注册没有 deregister 就关闭，之后不会再有事件，也不会让 poll 一直立即返回
+        registrator
+            .reregister(
+                &SourceFd(&fd),
+                TOKEN + 1,
+                Interests::READABLE | Interests::LEVEL,
+            )
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        drop(reused);
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(events.is_empty());
+
+        // timerfd、signalfd、eventfd 共用同一个匿名 inode, fd 号被复用时同样要能重新注册
+        let timer = Timer::new(Clock::Monotonic).unwrap();
+        let fd = timer.as_raw_fd();
+        registrator
+            .register(&timer, TOKEN, Interests::READABLE)
+            .unwrap();
+        let new = Timer::new(Clock::Monotonic).unwrap();
+        new.set_timeout(Duration::from_millis(1)).unwrap();
+        // 之后 timer 持有的 fd 号指向新的定时器
+        assert_eq!(fd, unsafe { dup2(new.as_raw_fd(), fd) });
+        drop(new);
+        registrator
+            .register(&timer, TOKEN + 1, Interests::READABLE)
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(TOKEN + 1, events[0].id());
+        assert_eq!(1, timer.read().unwrap());
+        assert_eq!(
+            io::ErrorKind::AlreadyExists,
+            registrator
+                .register(&timer, TOKEN, Interests::READABLE)
+                .unwrap_err()
+                .kind()
+        );
+        registrator.deregister(&timer).unwrap();
+
+        let signals = Signals::new(&[Signals::SIGUSR2]).unwrap();
+        registrator
+            .register(&signals, TOKEN, Interests::READABLE)
+            .unwrap();
+        let new = Signals::new(&[Signals::SIGUSR2]).unwrap();
+        assert_eq!(signals.as_raw_fd(), unsafe {
+            dup2(new.as_raw_fd(), signals.as_raw_fd())
+        });
+        drop(new);
+        registrator
+            .register(&signals, TOKEN + 1, Interests::READABLE)
+            .unwrap();
+        registrator.deregister(&signals).unwrap();
+
+        // Waker 释放时会 deregister, 同一个 token 可以重新创建
+        for _ in 0..2 {
+            let waker = Waker::new(&poll, WAKE_TOKEN).unwrap();
+            waker.wake().unwrap();
+            poll.poll(&mut events, Some(Duration::from_millis(1000)))
+                .unwrap();
+            assert_eq!(1, events.len());
+            assert_eq!(WAKE_TOKEN, events[0].id());
+        }
+    }
+}
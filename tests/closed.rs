@@ -0,0 +1,126 @@
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpStream, UnixStream, Waker};
+
+const TOKEN: usize = 140;
+
+//  cargo test closed_error -- --nocapture
+#[test]
+fn closed_error() {
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    let mut events = Events::with_capacity(16);
+    assert!(!poll.is_closed());
+    assert!(!registrator.is_closed());
+
+    // 其他线程关闭之后，阻塞中的 poll 返回关闭的错误
+    let handle = {
+        let registrator = poll.registrator();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            registrator.close_loop().unwrap();
+        })
+    };
+    let err = poll.poll(&mut events, None).unwrap_err();
+    handle.join().unwrap();
+    println!("Got {}", err);
+    assert!(tinymio::is_closed(&err));
+    assert_ne!(io::ErrorKind::Interrupted, err.kind());
+    assert!(poll.is_closed());
+    assert!(registrator.is_closed());
+
+    // 之后的注册和再次关闭也返回同样的错误
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let err = registrator
+        .register(&stream, TOKEN, Interests::READABLE)
+        .unwrap_err();
+    assert!(tinymio::is_closed(&err));
+    assert!(tinymio::is_closed(&registrator.close_loop().unwrap_err()));
+    assert!(tinymio::is_closed(
+        &poll
+            .poll(&mut events, Some(Duration::from_millis(10)))
+            .unwrap_err()
+    ));
+}
+
+//  cargo test closed_not_interrupted -- --nocapture
+#[test]
+fn closed_not_interrupted() {
+    // 其他错误不会被当成关闭
+    assert!(!tinymio::is_closed(&io::Error::from(
+        io::ErrorKind::Interrupted
+    )));
+    assert!(!tinymio::is_closed(&io::Error::other(
+        "Poll instance closed"
+    )));
+}
+
+//  cargo test closed_final_events -- --nocapture
+#[test]
+fn closed_final_events() {
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    let mut events = Events::with_capacity(16);
+    let (reader, mut writer) = UnixStream::pair().unwrap();
+    registrator
+        .register(&reader, TOKEN, Interests::READABLE | Interests::LEVEL)
+        .unwrap();
+
+    // 和关闭同时就绪的事件仍然交给用户，关闭自己的唤醒事件被过滤掉
+    writer.write_all(b"last").unwrap();
+    registrator.close_loop().unwrap();
+    assert!(poll.is_closed());
+    assert_eq!(1, poll.poll(&mut events, None).unwrap());
+    assert_eq!(TOKEN, events[0].id());
+    assert!(events[0].is_readable());
+
+    // 下一次 poll 才报告关闭
+    let err = poll.poll(&mut events, None).unwrap_err();
+    assert!(tinymio::is_closed(&err));
+    assert!(events.is_empty());
+}
+
+//  cargo test closed_reserved_token -- --nocapture
+#[test]
+fn closed_reserved_token() {
+    let poll = Poll::new().unwrap();
+    let (reader, _writer) = UnixStream::pair().unwrap();
+
+    // usize::MAX 留给 close_loop 使用
+    let err = poll
+        .registrator()
+        .register(&reader, usize::MAX, Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    let err = Waker::new(&poll, usize::MAX).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+}
+
+//  cargo test closed_after_drop -- --nocapture
+#[test]
+fn closed_after_drop() {
+    let poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    let (reader, _writer) = UnixStream::pair().unwrap();
+    registrator
+        .register(&reader, TOKEN, Interests::READABLE)
+        .unwrap();
+
+    // Poll 释放之后 Registrator 仍然可以在其他线程使用，只是所有操作都返回关闭的错误
+    drop(poll);
+    let handle = thread::spawn(move || {
+        assert!(registrator.is_closed());
+        let err = registrator
+            .reregister(&reader, TOKEN, Interests::READABLE)
+            .unwrap_err();
+        assert!(tinymio::is_closed(&err));
+        assert!(tinymio::is_closed(
+            &registrator.deregister(&reader).unwrap_err()
+        ));
+        assert!(tinymio::is_closed(&registrator.close_loop().unwrap_err()));
+    });
+    handle.join().unwrap();
+}
@@ -0,0 +1,66 @@
+// 断言的是 epoll 的 HUP/RDHUP 语义和 Debug 输出，kqueue 上行为不同
+#![cfg(target_os = "linux")]
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpStream};
+
+const TOKEN: usize = 50;
+
+//  cargo test read_closed -- --nocapture
+#[test]
+fn read_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, TOKEN, Interests::READABLE)
+        .unwrap();
+
+    // 对端只关闭写方向
+    server.shutdown(Shutdown::Write).unwrap();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    let event = &events[0];
+    println!("Got event: {:?}", event);
+    assert!(event.is_readable());
+    assert!(event.is_read_closed());
+    assert!(!event.is_write_closed());
+    assert!(!event.is_error());
+    assert!(format!("{:?}", event).contains("EPOLLRDHUP"));
+}
+
+//  cargo test reset_by_peer -- --nocapture
+#[test]
+fn reset_by_peer() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+
+    stream.write_all(b"hello").unwrap();
+    // 只读走一部分，缓冲区里还有数据时关闭会发送 RST
+    let mut buf = [0; 1];
+    server.read_exact(&mut buf).unwrap();
+    drop(server);
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, TOKEN, Interests::READABLE)
+        .unwrap();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    let event = &events[0];
+    println!("Got event: {:?}", event);
+    assert_eq!(TOKEN, event.id());
+    assert!(event.is_error());
+    assert!(event.is_read_closed());
+    assert!(event.is_write_closed());
+    assert!(!event.is_priority());
+}
@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, SourceFd};
+
+// 注册四个一直可读的水平触发事件源
+fn ready_sources(poll: &Poll) -> Vec<(UnixStream, UnixStream)> {
+    let registrator = poll.registrator();
+    (0..4)
+        .map(|token| {
+            let (reader, mut writer) = UnixStream::pair().unwrap();
+            writer.write_all(b"ready").unwrap();
+            registrator
+                .register(
+                    &SourceFd(&reader.as_raw_fd()),
+                    token,
+                    Interests::READABLE | Interests::LEVEL,
+                )
+                .unwrap();
+            (reader, writer)
+        })
+        .collect()
+}
+
+//  cargo test events_capacity -- --nocapture
+#[test]
+fn events_capacity() {
+    let mut poll = Poll::new().unwrap();
+    let _sources = ready_sources(&poll);
+
+    // 即使有四个就绪事件，每次也只返回缓冲区容量那么多
+    let mut events = Events::with_capacity(2);
+    assert_eq!(
+        2,
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap()
+    );
+    assert_eq!(2, events.len());
+    assert_eq!(2, events.iter().count());
+    assert_eq!(2, events.capacity());
+
+    events.clear();
+    assert!(events.is_empty());
+    assert!(events.get(0).is_none());
+
+    // 容量为 0 时至少可以取到一个事件
+    let mut events = Events::with_capacity(0);
+    assert_eq!(
+        1,
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap()
+    );
+}
+
+//  cargo test events_growable -- --nocapture
+#[test]
+fn events_growable() {
+    let mut poll = Poll::new().unwrap();
+    let _sources = ready_sources(&poll);
+
+    // 缓冲区被填满之后容量翻倍
+    let mut events = Events::growable(2);
+    assert_eq!(
+        2,
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap()
+    );
+    assert!(events.capacity() >= 4);
+    assert_eq!(
+        4,
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap()
+    );
+
+    let mut tokens: Vec<_> = (&events).into_iter().map(|e| e.id()).collect();
+    tokens.sort();
+    assert_eq!(vec![0, 1, 2, 3], tokens);
+}
@@ -0,0 +1,50 @@
+#![cfg(target_os = "linux")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tinymio::{Events, Poll};
+
+const SIGUSR1: i32 = 10;
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn pthread_self() -> usize;
+    fn pthread_kill(thread: usize, sig: i32) -> i32;
+}
+
+extern "C" fn on_signal(_: i32) {}
+
+//  cargo test interrupted_deadline -- --nocapture
+#[test]
+fn interrupted_deadline() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+
+    // 每 20ms 打断一次 poll, 重试时如果还用原来的超时就永远等不完
+    unsafe { signal(SIGUSR1, on_signal) };
+    let target = unsafe { pthread_self() };
+    let done = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(20));
+                unsafe { pthread_kill(target, SIGUSR1) };
+            }
+        })
+    };
+
+    let timeout = Duration::from_millis(200);
+    let start = Instant::now();
+    poll.poll(&mut events, Some(timeout)).unwrap();
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::SeqCst);
+    handle.join().unwrap();
+
+    println!("elapsed: {:?}", elapsed);
+    assert!(events.is_empty());
+    assert!(elapsed >= timeout);
+    assert!(elapsed < Duration::from_millis(1000));
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "log")]
+use std::sync::Mutex;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, UdpSocket};
+
+//  cargo test --features log instrumentation -- --nocapture
+static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Recorder;
+
+impl log::Log for Recorder {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.target() == "tinymio" {
+            RECORDS.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn instrumentation() {
+    log::set_logger(&Recorder).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    registrator
+        .register(&socket, 120, Interests::WRITABLE)
+        .unwrap();
+    registrator
+        .register(&socket, 120, Interests::WRITABLE)
+        .unwrap_err();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+
+    let records = RECORDS.lock().unwrap();
+    println!("{:#?}", records);
+    assert!(records
+        .iter()
+        .any(|r| r.starts_with("register") && r.contains("token=120")));
+    assert!(records
+        .iter()
+        .any(|r| r.starts_with("register") && r.contains("failed")));
+    assert!(records.iter().any(|r| r.contains("got 1 events")));
+}
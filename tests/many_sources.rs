@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, SourceFd};
+
+// 每个事件源占两个 fd, 要能在默认的 `ulimit -n 1024` 下运行
+const SOURCES: usize = 400;
+
+//  cargo test many_sources -- --nocapture
+#[test]
+fn many_sources() {
+    many_sources_on(Poll::new().unwrap());
+}
+
+//  cargo test --features io-uring many_sources_io_uring -- --nocapture
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[test]
+fn many_sources_io_uring() {
+    // 内核不支持 io_uring 时跳过
+    match Poll::with_backend(tinymio::Backend::IoUring) {
+        Ok(poll) => many_sources_on(poll),
+        Err(e) => println!("io_uring unavailable: {}", e),
+    }
+}
+
+fn many_sources_on(mut poll: Poll) {
+    let registrator = poll.registrator();
+
+    // 注册数量超过一次能提交的数量，也超过一次能取回的事件数量
+    let pairs: Vec<_> = (0..SOURCES)
+        .map(|token| {
+            let (reader, mut writer) = UnixStream::pair().unwrap();
+            writer.write_all(b"ready").unwrap();
+            registrator
+                .register(
+                    &SourceFd(&reader.as_raw_fd()),
+                    token,
+                    Interests::READABLE | Interests::LEVEL,
+                )
+                .unwrap();
+            (reader, writer)
+        })
+        .collect();
+
+    // 水平触发的事件轮流返回，每个事件源都能被取到
+    let mut events = Events::with_capacity(64);
+    let mut seen = HashSet::new();
+    for _ in 0..SOURCES.div_ceil(64) {
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(64, events.len());
+        seen.extend(events.iter().map(|event| event.id()));
+    }
+    assert_eq!(SOURCES, seen.len());
+
+    // 全部移除之后不会再有事件
+    for (reader, _) in &pairs {
+        registrator
+            .deregister(&SourceFd(&reader.as_raw_fd()))
+            .unwrap();
+    }
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+    assert!(events.is_empty());
+}
@@ -1,13 +1,16 @@
+use std::cell::Cell;
 use std::io::{self, Read, Write};
+use std::rc::Rc;
 use std::sync::mpsc::channel;
 use std::thread;
+use std::time::Duration;
 use tinymio::{Events, Interests, Poll, TcpStream};
 
 //  cargo test multiple_registrations -- --nocapture
 #[test]
 fn multiple_registrations() {
     let mut poll = Poll::new().unwrap();
-    let registrator = poll.registrator();
+    let registrator = Rc::new(poll.registrator());
     let (event_tx, event_rx) = channel();
     let mut runtime = Runtime { events: vec![] };
 
@@ -21,10 +24,10 @@ fn multiple_registrations() {
             println!("Polling");
             let will_close = false;
             println!("poll: {:?}", poll);
-            match poll.poll(&mut events, Some(200)) {
+            match poll.poll(&mut events, Some(Duration::from_millis(200))) {
                 Ok(..) => (),
-                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {
-                    println!("INTERRUPTED: {}", err);
+                Err(ref err) if tinymio::is_closed(err) => {
+                    println!("CLOSED: {}", err);
                     break;
                 }
                 Err(err) => panic!("Poll error: {:?}, {}", err.kind(), err),
@@ -43,6 +46,7 @@ fn multiple_registrations() {
 
     // 初始化两个tcp连接并发送请求
     let mut stream1 = TcpStream::connect("127.0.0.1:9527").unwrap();
+    wait_connected(&stream1);
     let request1 = format!(
         "GET /delay/{}/url/http://delay.com HTTP/1.1\r\n\
              Host: localhost\r\n\
@@ -56,6 +60,7 @@ fn multiple_registrations() {
         .expect("Stream write err.");
 
     let mut stream2 = TcpStream::connect("127.0.0.1:9527").unwrap();
+    wait_connected(&stream2);
     let request2 = format!(
         "GET /delay/{}/url/http://delay.com HTTP/1.1\r\n\
              Host: localhost\r\n\
@@ -70,32 +75,63 @@ fn multiple_registrations() {
 
     // 注册两个event到内核事件队列
     registrator
-        .register(&mut stream1, token1, Interests::READABLE)
+        .register(&stream1, token1, Interests::READABLE)
         .unwrap();
     registrator
-        .register(&mut stream2, token2, Interests::READABLE)
+        .register(&stream2, token2, Interests::READABLE)
         .unwrap();
 
     // 注册两个socket可读后的后续处理逻辑
+    // 读取是非阻塞的，响应没有读完时重新打开监听，读到 EOF 之后才算完成
+    let finished = Rc::new(Cell::new(0));
+    let mut buffer1 = vec![];
+    let registrator1 = registrator.clone();
+    let finished1 = finished.clone();
     runtime.spawn(token1, move || {
-        let mut buffer = String::new();
-        stream1.read_to_string(&mut buffer).unwrap();
-        println!("Get response from stream1: {}", buffer);
+        match stream1.read_to_end(&mut buffer1) {
+            Ok(..) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                registrator1
+                    .reregister(&stream1, token1, Interests::READABLE)
+                    .unwrap();
+                return;
+            }
+            Err(e) => panic!("Stream read err: {:?}, {}", e.kind(), e),
+        }
+        println!(
+            "Get response from stream1: {}",
+            String::from_utf8_lossy(&buffer1)
+        );
+        finished1.set(finished1.get() + 1);
     });
 
+    let mut buffer2 = vec![];
+    let registrator2 = registrator.clone();
+    let finished2 = finished.clone();
     runtime.spawn(token2, move || {
-        let mut buffer = String::new();
-        stream2.read_to_string(&mut buffer).unwrap();
-        println!("Get response from stream2: {}", buffer);
+        match stream2.read_to_end(&mut buffer2) {
+            Ok(..) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                registrator2
+                    .reregister(&stream2, token2, Interests::READABLE)
+                    .unwrap();
+                return;
+            }
+            Err(e) => panic!("Stream read err: {:?}, {}", e.kind(), e),
+        }
+        println!(
+            "Get response from stream2: {}",
+            String::from_utf8_lossy(&buffer2)
+        );
+        finished2.set(finished2.get() + 1);
     });
 
     // 启动runtime main loop, 持续接收registrator的通知，唤醒对应的代码进行处理
-    let mut counter = 0;
+    // 两个响应都读完之后关闭事件循环
     while let Ok(received_evt_id) = event_rx.recv() {
-        counter += 1;
         println!("Received Evt id: {}", received_evt_id);
         runtime.run(received_evt_id);
-        if counter == 2 {
+        if finished.get() == 2 {
             registrator.close_loop().unwrap()
         }
     }
@@ -104,6 +140,21 @@ fn multiple_registrations() {
     println!("EXITING");
 }
 
+// connect 是非阻塞的，等连接建立（socket 可写）之后再发送请求
+fn wait_connected(stream: &TcpStream) {
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    registrator
+        .register(stream, 0, Interests::WRITABLE)
+        .expect("registration err.");
+    let mut events = Events::with_capacity(1);
+    poll.poll(&mut events, None).expect("poll err.");
+    if let Some(e) = stream.take_error().expect("take error err.") {
+        panic!("Connect err: {}", e);
+    }
+    registrator.deregister(stream).expect("deregistration err.");
+}
+
 struct Runtime {
     events: Vec<(usize, Box<dyn FnMut()>)>,
 }
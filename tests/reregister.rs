@@ -0,0 +1,56 @@
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpStream};
+
+const TOKEN: usize = 30;
+const NEW_TOKEN: usize = 31;
+
+//  cargo test reregister -- --nocapture
+#[test]
+fn reregister_and_deregister() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    server.write_all(b"hello").unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    let mut events = Events::with_capacity(16);
+
+    registrator
+        .register(&stream, TOKEN, Interests::READABLE)
+        .unwrap();
+    poll.poll(&mut events, None).unwrap();
+    assert_eq!(TOKEN, events[0].id());
+
+    // oneshot 触发之后不会再有事件，即使数据还没有被读走
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+    assert!(events.is_empty());
+
+    // 重复注册同一个 fd
+    let err = registrator
+        .register(&stream, TOKEN, Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+
+    // 重新打开监听，并换一个 token
+    registrator
+        .reregister(&stream, NEW_TOKEN, Interests::READABLE)
+        .unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(NEW_TOKEN, events[0].id());
+
+    registrator.deregister(&stream).unwrap();
+
+    // 移除之后再修改或者移除都会失败
+    let err = registrator
+        .reregister(&stream, TOKEN, Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+    let err = registrator.deregister(&stream).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+}
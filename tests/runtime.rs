@@ -0,0 +1,230 @@
+use std::cell::Cell;
+use std::future::{self, Future};
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+use tinymio::runtime::{self, Runtime};
+use tinymio::TcpStream;
+
+//  cargo test runtime_spawn -- --nocapture
+#[test]
+fn runtime_spawn() {
+    let counter = Rc::new(Cell::new(0));
+    let result = runtime::block_on({
+        let counter = counter.clone();
+        async move {
+            let handles: Vec<_> = (0..3)
+                .map(|i| {
+                    let counter = counter.clone();
+                    runtime::spawn(async move {
+                        counter.set(counter.get() + 1);
+                        i * 2
+                    })
+                })
+                .collect();
+            let mut sum = 0;
+            for handle in handles {
+                sum += handle.await;
+            }
+            sum
+        }
+    });
+    assert_eq!(6, result);
+    assert_eq!(3, counter.get());
+}
+
+//  cargo test runtime_block_on_again -- --nocapture
+#[test]
+fn runtime_block_on_again() {
+    // 第一次 block_on 返回时任务还没有运行，留在运行时里，下一次 block_on 时继续执行
+    let mut runtime = Runtime::new().unwrap();
+    let handle = runtime.block_on(future::poll_fn(|_| {
+        Poll::Ready(runtime::spawn(async { 42 }))
+    }));
+    assert_eq!(42, runtime.block_on(handle));
+}
+
+//  cargo test runtime_drop_in_other_runtime -- --nocapture
+#[test]
+fn runtime_drop_in_other_runtime() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // 在第一个运行时里注册，拿到 token 0
+        let stream = TcpStream::connect(addr).unwrap();
+        let _server = listener.accept().unwrap();
+        runtime::block_on(future::poll_fn(|cx| {
+            assert!(stream.poll_read(cx, &mut [0; 8]).is_pending());
+            Poll::Ready(())
+        }));
+
+        // 第二个运行时里等待的事件源同样是 token 0, 释放别的运行时注册的 stream 不能影响它
+        let other = TcpStream::connect(addr).unwrap();
+        let (mut other_server, _) = listener.accept().unwrap();
+        let n = runtime::block_on(async move {
+            let handle = runtime::spawn(async move { other.read_async(&mut [0; 8]).await });
+            // 让出一次，等任务注册好之后再释放
+            let mut yielded = false;
+            future::poll_fn(|cx| {
+                if yielded {
+                    return Poll::Ready(());
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .await;
+            drop(stream);
+            other_server.write_all(b"ping").unwrap();
+            handle.await.unwrap()
+        });
+        tx.send(n).unwrap();
+    });
+    assert_eq!(4, rx.recv_timeout(Duration::from_secs(5)).unwrap());
+}
+
+// 在其他线程上 sleep 之后唤醒任务
+struct Sleep {
+    started: bool,
+    done: Rc<Cell<bool>>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.started {
+            self.done.set(true);
+            return Poll::Ready(());
+        }
+        self.started = true;
+        let waker = cx.waker().clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            waker.wake();
+        });
+        Poll::Pending
+    }
+}
+
+//  cargo test runtime_cross_thread_wake -- --nocapture
+#[test]
+fn runtime_cross_thread_wake() {
+    let done = Rc::new(Cell::new(false));
+    let start = Instant::now();
+    runtime::block_on(Sleep {
+        started: false,
+        done: done.clone(),
+    });
+    assert!(done.get());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+//  cargo test runtime_tcp_echo -- --nocapture
+#[test]
+fn runtime_tcp_echo() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).unwrap();
+        // 晚一点回复，让客户端先进入等待
+        thread::sleep(Duration::from_millis(100));
+        stream.write_all(&buf).unwrap();
+    });
+
+    let response = runtime::block_on(async move {
+        // 连接还没有建立时写入会先等待可写事件
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.write_all_async(b"hello").await.unwrap();
+        let mut response = vec![];
+        stream.read_to_end_async(&mut response).await.unwrap();
+        response
+    });
+    assert_eq!(b"hello", &response[..]);
+    server.join().unwrap();
+}
+
+//  cargo test runtime_concurrent_requests -- --nocapture
+#[test]
+fn runtime_concurrent_requests() {
+    // 两个请求在服务端各延迟 2 秒，同时等待的话总共只需要 2 秒左右
+    let start = Instant::now();
+    let responses = runtime::block_on(async {
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                runtime::spawn(async {
+                    let stream = TcpStream::connect("127.0.0.1:9527").unwrap();
+                    let request = format!(
+                        "GET /delay/{}/url/http://delay.com HTTP/1.1\r\n\
+                             Host: localhost\r\n\
+                             Connection: close\r\n\
+                             \r\n",
+                        2000,
+                    );
+                    stream.write_all_async(request.as_bytes()).await.unwrap();
+                    let mut response = vec![];
+                    stream.read_to_end_async(&mut response).await.unwrap();
+                    response
+                })
+            })
+            .collect();
+        let mut responses = vec![];
+        for handle in handles {
+            responses.push(handle.await);
+        }
+        responses
+    });
+
+    let elapsed = start.elapsed();
+    println!("elapsed: {:?}", elapsed);
+    for response in &responses {
+        println!("Got {}", String::from_utf8_lossy(response));
+        assert!(!response.is_empty());
+    }
+    assert!(elapsed < Duration::from_millis(3500));
+}
+
+//  cargo test runtime_outside -- --nocapture
+#[test]
+fn runtime_outside() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let _server = listener.accept().unwrap();
+
+    // 不在运行时里时没有办法等待事件
+    let waker = futures_noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut buf = [0; 8];
+    match stream.poll_read(&mut cx, &mut buf) {
+        Poll::Ready(Err(e)) => assert_eq!(io::ErrorKind::Other, e.kind()),
+        other => panic!("unexpected: {:?}", other),
+    }
+
+    // 同一个 stream 之后仍然可以在运行时里使用
+    runtime::block_on(async {
+        let read = future::poll_fn(|cx| match stream.poll_read(cx, &mut buf) {
+            Poll::Pending => Poll::Ready(true),
+            Poll::Ready(_) => Poll::Ready(false),
+        });
+        assert!(read.await);
+    });
+}
+
+fn futures_noop_waker() -> std::task::Waker {
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Noop;
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+    std::task::Waker::from(Arc::new(Noop))
+}
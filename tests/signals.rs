@@ -0,0 +1,96 @@
+#![cfg(target_os = "linux")]
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, Signals};
+
+const TOKEN: usize = 130;
+
+extern "C" {
+    fn pthread_self() -> usize;
+    fn pthread_kill(thread: usize, sig: i32) -> i32;
+}
+
+// 发给指定线程的信号，测试线程之外的线程没有屏蔽这些信号，不能发给整个进程
+fn send(thread: usize, signal: i32) {
+    assert_eq!(0, unsafe { pthread_kill(thread, signal) });
+}
+
+//  cargo test signals_poll -- --nocapture
+#[test]
+fn signals_poll() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    let signals = Signals::new(&[Signals::SIGUSR1]).unwrap();
+    poll.registrator()
+        .register(&signals, TOKEN, Interests::READABLE | Interests::EDGE)
+        .unwrap();
+    assert_eq!(
+        io::ErrorKind::WouldBlock,
+        signals.read().unwrap_err().kind()
+    );
+
+    // 阻塞在 poll 上的时候收到信号
+    let target = unsafe { pthread_self() };
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        send(target, Signals::SIGUSR1);
+    });
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    handle.join().unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(TOKEN, events[0].id());
+    assert!(events[0].is_readable());
+
+    let info = signals.read().unwrap();
+    println!("Got {:?}", info);
+    assert_eq!(Signals::SIGUSR1, info.signal());
+    assert_eq!(std::process::id(), info.pid());
+    assert_eq!(
+        io::ErrorKind::WouldBlock,
+        signals.read().unwrap_err().kind()
+    );
+}
+
+//  cargo test signals_multiple -- --nocapture
+#[test]
+fn signals_multiple() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    let signals = Signals::new(&[Signals::SIGHUP, Signals::SIGTERM]).unwrap();
+    poll.registrator()
+        .register(&signals, TOKEN, Interests::READABLE | Interests::LEVEL)
+        .unwrap();
+
+    let target = unsafe { pthread_self() };
+    send(target, Signals::SIGTERM);
+    send(target, Signals::SIGHUP);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+
+    // 等待中的信号按编号从小到大读出
+    let mut received = vec![];
+    loop {
+        match signals.read() {
+            Ok(info) => received.push(info.signal()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => panic!("read err: {}", e),
+        }
+    }
+    assert_eq!(vec![Signals::SIGHUP, Signals::SIGTERM], received);
+
+    // 读完之后不再有事件
+    poll.poll(&mut events, Some(Duration::from_millis(50)))
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+//  cargo test signals_invalid -- --nocapture
+#[test]
+fn signals_invalid() {
+    let err = Signals::new(&[0]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+}
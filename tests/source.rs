@@ -0,0 +1,60 @@
+use std::io::Write;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, Source, SourceFd};
+
+const FD_TOKEN: usize = 70;
+const CUSTOM_TOKEN: usize = 71;
+
+// 下游 crate 自己的基于 fd 的类型
+struct Pipe {
+    inner: UnixStream,
+}
+
+impl Source for Pipe {
+    fn raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+//  cargo test source -- --nocapture
+#[test]
+fn register_any_fd() {
+    let (reader, mut writer) = UnixStream::pair().unwrap();
+    reader.set_nonblocking(true).unwrap();
+    let (custom, mut custom_writer) = UnixStream::pair().unwrap();
+    let custom = Pipe { inner: custom };
+
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    registrator
+        .register(
+            &SourceFd(&reader.as_raw_fd()),
+            FD_TOKEN,
+            Interests::READABLE,
+        )
+        .unwrap();
+    registrator
+        .register(&custom, CUSTOM_TOKEN, Interests::READABLE)
+        .unwrap();
+
+    writer.write_all(b"hello").unwrap();
+    custom_writer.write_all(b"world").unwrap();
+
+    let mut events = Events::with_capacity(16);
+    let mut tokens = vec![];
+    while tokens.len() < 2 {
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert!(!events.is_empty(), "timed out waiting for events");
+        tokens.extend(events.iter().map(|e| e.id()));
+    }
+    tokens.sort();
+    assert_eq!(vec![FD_TOKEN, CUSTOM_TOKEN], tokens);
+
+    registrator
+        .deregister(&SourceFd(&reader.as_raw_fd()))
+        .unwrap();
+    registrator.deregister(&custom).unwrap();
+}
@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+use std::net;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpListener};
+
+const LISTENER_TOKEN: usize = 80;
+
+//  cargo test tcp_listener -- --nocapture
+#[test]
+fn tcp_listener_accept() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // 还没有连接时不会阻塞
+    let err = listener.accept().err().unwrap();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&listener, LISTENER_TOKEN, Interests::READABLE)
+        .unwrap();
+
+    let mut client = net::TcpStream::connect(addr).unwrap();
+    client.write_all(b"hello").unwrap();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(LISTENER_TOKEN, events[0].id());
+    assert!(events[0].is_readable());
+
+    let (_stream, peer) = listener.accept().unwrap();
+    assert_eq!(client.local_addr().unwrap(), peer);
+
+    // 等待队列已经空了
+    let err = listener.accept().err().unwrap();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+}
@@ -0,0 +1,140 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpStream};
+
+const TOKEN: usize = 110;
+
+//  cargo test read_would_block -- --nocapture
+#[test]
+fn read_would_block() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+
+    // 没有数据时读取不会阻塞
+    let mut buf = [0; 64];
+    let err = stream.read(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, TOKEN, Interests::READABLE | Interests::EDGE)
+        .unwrap();
+    server.write_all(b"hello").unwrap();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(TOKEN, events[0].id());
+
+    // 边沿触发需要一直读到 WouldBlock, 读完之后 socket 仍然是非阻塞的
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(b"hello", &buf[..n]);
+    let err = stream.read(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+}
+
+//  cargo test connect_non_blocking -- --nocapture
+#[test]
+fn connect_non_blocking() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+    // 连接完成时 socket 变为可写
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, TOKEN, Interests::WRITABLE)
+        .unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(TOKEN, events[0].id());
+    assert!(events[0].is_writable());
+    assert!(stream.take_error().unwrap().is_none());
+}
+
+//  cargo test connect_refused -- --nocapture
+#[test]
+fn connect_refused() {
+    // 拿到一个没有监听的端口
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let stream = match TcpStream::connect(addr) {
+        Ok(stream) => stream,
+        // 本地连接也可能在 connect 调用中就失败
+        Err(e) => {
+            assert_eq!(io::ErrorKind::ConnectionRefused, e.kind());
+            return;
+        }
+    };
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, TOKEN, Interests::WRITABLE)
+        .unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(TOKEN, events[0].id());
+    assert!(events[0].is_error());
+
+    let err = stream.take_error().unwrap().expect("connect should fail");
+    assert_eq!(io::ErrorKind::ConnectionRefused, err.kind());
+}
+
+//  cargo test socket_options -- --nocapture
+#[test]
+fn socket_options() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    assert_eq!(listener.local_addr().unwrap(), stream.peer_addr().unwrap());
+    assert_eq!(server.peer_addr().unwrap(), stream.local_addr().unwrap());
+
+    stream.set_nodelay(true).unwrap();
+    assert!(stream.nodelay().unwrap());
+
+    stream.set_ttl(42).unwrap();
+    assert_eq!(42, stream.ttl().unwrap());
+
+    stream.set_keepalive(true).unwrap();
+    assert!(stream.keepalive().unwrap());
+    stream.set_keepalive(false).unwrap();
+    assert!(!stream.keepalive().unwrap());
+
+    assert_eq!(None, stream.linger().unwrap());
+    stream.set_linger(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(Some(Duration::from_secs(5)), stream.linger().unwrap());
+    // 超出范围的时长不能回绕成负数或者 0
+    stream.set_linger(Some(Duration::MAX)).unwrap();
+    assert!(stream.linger().unwrap().unwrap() > Duration::from_secs(5));
+    stream.set_linger(None).unwrap();
+    assert_eq!(None, stream.linger().unwrap());
+
+    // 内核会调整缓冲区大小，只检查不小于设置的值
+    stream.set_send_buffer_size(64 * 1024).unwrap();
+    assert!(stream.send_buffer_size().unwrap() >= 64 * 1024);
+    stream.set_recv_buffer_size(64 * 1024).unwrap();
+    assert!(stream.recv_buffer_size().unwrap() >= 64 * 1024);
+
+    // 超出范围的大小不能回绕成 0, linux 会把它限制在 wmem_max/rmem_max
+    #[cfg(target_os = "linux")]
+    {
+        stream.set_send_buffer_size(1 << 32).unwrap();
+        assert!(stream.send_buffer_size().unwrap() >= 64 * 1024);
+        stream.set_recv_buffer_size(1 << 32).unwrap();
+        assert!(stream.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    assert!(stream.take_error().unwrap().is_none());
+
+    // 关闭写方向之后对端读到 EOF
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(0, (&server).read(&mut buf).unwrap());
+}
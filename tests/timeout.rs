@@ -0,0 +1,29 @@
+use std::time::{Duration, Instant};
+use tinymio::{Events, Poll};
+
+//  cargo test poll_timeout -- --nocapture
+#[test]
+fn poll_timeout() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+
+    // 不足一毫秒的部分向上取整，不会提前返回
+    for timeout in [
+        Duration::from_micros(1500),
+        Duration::from_micros(10),
+        Duration::from_millis(20),
+    ] {
+        let start = Instant::now();
+        poll.poll(&mut events, Some(timeout)).unwrap();
+        let elapsed = start.elapsed();
+        println!("timeout: {:?}, elapsed: {:?}", timeout, elapsed);
+        assert!(elapsed >= timeout);
+        assert!(events.is_empty());
+    }
+
+    // 超时为 0 时立即返回
+    let start = Instant::now();
+    poll.poll(&mut events, Some(Duration::ZERO)).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert!(events.is_empty());
+}
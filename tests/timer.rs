@@ -0,0 +1,121 @@
+#![cfg(target_os = "linux")]
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use tinymio::{Clock, Events, Expiration, Interests, Poll, Timer};
+
+const TOKEN: usize = 120;
+
+//  cargo test timer_oneshot -- --nocapture
+#[test]
+fn timer_oneshot() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    let timer = Timer::new(Clock::Monotonic).unwrap();
+    poll.registrator()
+        .register(&timer, TOKEN, Interests::READABLE | Interests::EDGE)
+        .unwrap();
+
+    // 没有启动的定时器不会到期
+    assert_eq!(io::ErrorKind::WouldBlock, timer.read().unwrap_err().kind());
+
+    let start = Instant::now();
+    timer.set_timeout(Duration::from_millis(50)).unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(1, events.len());
+    assert_eq!(TOKEN, events[0].id());
+    assert!(events[0].is_readable());
+    assert_eq!(1, timer.read().unwrap());
+
+    // 只触发一次
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+    assert!(events.is_empty());
+    assert_eq!(io::ErrorKind::WouldBlock, timer.read().unwrap_err().kind());
+}
+
+//  cargo test timer_interval -- --nocapture
+#[test]
+fn timer_interval() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    let timer = Timer::new(Clock::Boottime).unwrap();
+    assert_eq!(Clock::Boottime, timer.clock());
+    poll.registrator()
+        .register(&timer, TOKEN, Interests::READABLE | Interests::EDGE)
+        .unwrap();
+    timer.set_interval(Duration::from_millis(20)).unwrap();
+
+    for _ in 0..3 {
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert!(timer.read().unwrap() >= 1);
+    }
+
+    // 没有及时读取的时候，到期次数会累加
+    thread::sleep(Duration::from_millis(100));
+    assert!(timer.read().unwrap() >= 3);
+
+    // 停止之后不再触发
+    timer.disarm().unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+    assert!(events.is_empty());
+    assert_eq!(io::ErrorKind::WouldBlock, timer.read().unwrap_err().kind());
+}
+
+//  cargo test timer_absolute -- --nocapture
+#[test]
+fn timer_absolute() {
+    for clock in [Clock::Monotonic, Clock::Boottime] {
+        println!("clock: {:?}", clock);
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let timer = Timer::new(clock).unwrap();
+        poll.registrator()
+            .register(&timer, TOKEN, Interests::READABLE | Interests::LEVEL)
+            .unwrap();
+
+        let start = Instant::now();
+        let deadline = timer.now().unwrap() + Duration::from_millis(50);
+        timer.set(Expiration::At(deadline), None).unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(1, events.len());
+        assert!(timer.now().unwrap() >= deadline);
+        assert_eq!(1, timer.read().unwrap());
+
+        // 已经过去的时刻立即到期，周期定时器之后继续触发
+        timer
+            .set(Expiration::At(deadline), Some(Duration::from_millis(20)))
+            .unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert!(timer.read().unwrap() >= 1);
+        timer.disarm().unwrap();
+    }
+}
+
+//  cargo test timer_invalid_interval -- --nocapture
+#[test]
+fn timer_invalid_interval() {
+    let timer = Timer::new(Clock::Monotonic).unwrap();
+    let err = timer
+        .set(
+            Expiration::After(Duration::from_millis(10)),
+            Some(Duration::ZERO),
+        )
+        .unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+    // 零超时也会到期，而不是停止定时器
+    timer.set_timeout(Duration::ZERO).unwrap();
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(1, timer.read().unwrap());
+}
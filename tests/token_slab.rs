@@ -0,0 +1,40 @@
+use tinymio::TokenSlab;
+
+//  cargo test token_slab -- --nocapture
+#[test]
+fn token_slab() {
+    let mut slab = TokenSlab::new();
+    assert!(slab.is_empty());
+
+    let a = slab.insert(String::from("a"));
+    let b = slab.insert_with(|token| format!("b-{}", token));
+    assert_ne!(a, b);
+    assert_eq!(2, slab.len());
+    assert_eq!("a", slab.get(a).unwrap());
+    assert_eq!(&format!("b-{}", b), slab.get(b).unwrap());
+
+    slab.get_mut(a).unwrap().push('a');
+    assert_eq!(Some(String::from("aa")), slab.remove(a));
+    assert_eq!(1, slab.len());
+
+    // 释放之后 token 过期
+    assert!(!slab.contains(a));
+    assert_eq!(None, slab.get(a));
+    assert_eq!(None, slab.remove(a));
+
+    // 复用同一个槽位，但是旧 token 仍然匹配不上
+    let c = slab.insert(String::from("c"));
+    assert_ne!(a, c);
+    assert_eq!(None, slab.get(a));
+    assert_eq!("c", slab.get(c).unwrap());
+
+    let mut tokens: Vec<_> = slab.iter().map(|(token, _)| token).collect();
+    tokens.sort();
+    let mut expected = vec![b, c];
+    expected.sort();
+    assert_eq!(expected, tokens);
+
+    // 从来没有分配过的 token
+    assert_eq!(None, slab.get(1000));
+    assert_eq!(None, slab.get(usize::MAX));
+}
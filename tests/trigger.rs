@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::net::TcpListener;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpStream, Trigger};
+
+const LEVEL_TOKEN: usize = 40;
+const EDGE_TOKEN: usize = 41;
+
+#[test]
+fn trigger_from_interests() {
+    assert_eq!(Trigger::Oneshot, Interests::READABLE.trigger());
+    assert_eq!(
+        Trigger::Level,
+        (Interests::READABLE | Interests::LEVEL).trigger()
+    );
+    assert_eq!(
+        Trigger::Edge,
+        (Interests::READABLE | Interests::WRITABLE | Interests::EDGE).trigger()
+    );
+}
+
+//  cargo test level_triggered -- --nocapture
+#[test]
+fn level_triggered() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    server.write_all(b"hello").unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, LEVEL_TOKEN, Interests::READABLE | Interests::LEVEL)
+        .unwrap();
+
+    // 数据一直没有读走，每次 poll 都会收到事件
+    let mut events = Events::with_capacity(16);
+    for _ in 0..3 {
+        poll.poll(&mut events, Some(Duration::from_millis(1000)))
+            .unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(LEVEL_TOKEN, events[0].id());
+    }
+}
+
+//  cargo test edge_triggered -- --nocapture
+#[test]
+fn edge_triggered() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    server.write_all(b"hello").unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, EDGE_TOKEN, Interests::READABLE | Interests::EDGE)
+        .unwrap();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(EDGE_TOKEN, events[0].id());
+
+    // 没有新的数据到达，不会再次通知
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+    assert!(events.is_empty());
+
+    // 新数据到达产生新的边沿，注册一直有效，不需要 reregister
+    server.write_all(b"world").unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(EDGE_TOKEN, events[0].id());
+}
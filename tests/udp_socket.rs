@@ -0,0 +1,66 @@
+use std::io;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, UdpSocket};
+
+const SENDER_TOKEN: usize = 90;
+const RECEIVER_TOKEN: usize = 91;
+
+//  cargo test udp_socket -- --nocapture
+#[test]
+fn udp_socket() {
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let receiver_addr = receiver.local_addr().unwrap();
+
+    // 没有数据时不会阻塞
+    let mut buf = [0; 64];
+    let err = receiver.recv_from(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    registrator
+        .register(&sender, SENDER_TOKEN, Interests::WRITABLE)
+        .unwrap();
+    registrator
+        .register(&receiver, RECEIVER_TOKEN, Interests::READABLE)
+        .unwrap();
+
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(SENDER_TOKEN, events[0].id());
+    assert!(events[0].is_writable());
+
+    sender.send_to(b"ping", receiver_addr).unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(RECEIVER_TOKEN, events[0].id());
+    assert!(events[0].is_readable());
+
+    // peek 不会把数据报从队列中取走
+    let (n, from) = receiver.peek_from(&mut buf).unwrap();
+    assert_eq!(b"ping", &buf[..n]);
+    assert_eq!(sender.local_addr().unwrap(), from);
+    let (n, from) = receiver.recv_from(&mut buf).unwrap();
+    assert_eq!(b"ping", &buf[..n]);
+    assert_eq!(sender.local_addr().unwrap(), from);
+    let err = receiver.recv_from(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    // connect 之后使用 send/recv
+    sender.connect(receiver_addr).unwrap();
+    receiver.connect(sender.local_addr().unwrap()).unwrap();
+    assert_eq!(receiver_addr, sender.peer_addr().unwrap());
+    sender.send(b"pong").unwrap();
+    registrator
+        .reregister(&receiver, RECEIVER_TOKEN, Interests::READABLE)
+        .unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(RECEIVER_TOKEN, events[0].id());
+    let n = receiver.recv(&mut buf).unwrap();
+    assert_eq!(b"pong", &buf[..n]);
+}
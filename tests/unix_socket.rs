@@ -0,0 +1,152 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use std::{env, fs, process};
+use tinymio::{Events, Interests, Poll, UnixDatagram, UnixListener, UnixStream};
+
+const LISTENER_TOKEN: usize = 100;
+const STREAM_TOKEN: usize = 101;
+const DATAGRAM_TOKEN: usize = 102;
+
+//  cargo test unix_listener -- --nocapture
+#[test]
+fn unix_listener_path() {
+    let path = env::temp_dir().join(format!("tinymio-{}.sock", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path).unwrap();
+    let err = listener.accept().err().unwrap();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&listener, LISTENER_TOKEN, Interests::READABLE)
+        .unwrap();
+
+    let mut client = UnixStream::connect(&path).unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(LISTENER_TOKEN, events[0].id());
+
+    let (mut server, _) = listener.accept().unwrap();
+    client.write_all(b"hello").unwrap();
+    poll.registrator()
+        .register(&server, STREAM_TOKEN, Interests::READABLE)
+        .unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(STREAM_TOKEN, events[0].id());
+
+    let mut buf = [0; 64];
+    let n = server.read(&mut buf).unwrap();
+    assert_eq!(b"hello", &buf[..n]);
+    let err = server.read(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    fs::remove_file(&path).unwrap();
+}
+
+//  cargo test unix_abstract -- --nocapture
+// 抽象命名空间只有 Linux 支持
+#[cfg(target_os = "linux")]
+#[test]
+fn unix_abstract_namespace() {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let name = format!("tinymio-abstract-{}", process::id());
+    let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+    let listener = UnixListener::bind_addr(&addr).unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&listener, LISTENER_TOKEN, Interests::READABLE)
+        .unwrap();
+
+    let _client = UnixStream::connect_addr(&addr).unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(LISTENER_TOKEN, events[0].id());
+    listener.accept().unwrap();
+
+    let local = listener.local_addr().unwrap();
+    assert_eq!(Some(name.as_bytes()), local.as_abstract_name());
+}
+
+//  cargo test unix_pair -- --nocapture
+#[test]
+fn unix_pair() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&b, STREAM_TOKEN, Interests::READABLE)
+        .unwrap();
+
+    a.write_all(b"ping").unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(STREAM_TOKEN, events[0].id());
+    let mut buf = [0; 4];
+    b.read_exact(&mut buf).unwrap();
+    assert_eq!(b"ping", &buf);
+
+    let (x, y) = UnixDatagram::pair().unwrap();
+    poll.registrator()
+        .register(&y, DATAGRAM_TOKEN, Interests::READABLE)
+        .unwrap();
+    let err = y.recv(&mut buf).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    x.send(b"pong").unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(DATAGRAM_TOKEN, events[0].id());
+    let n = y.recv(&mut buf).unwrap();
+    assert_eq!(b"pong", &buf[..n]);
+}
+
+//  cargo test unix_connect_backlog_full -- --nocapture
+// backlog 满了之后 connect 不能阻塞，Linux 上返回 WouldBlock
+#[cfg(target_os = "linux")]
+#[test]
+fn unix_connect_backlog_full() {
+    use std::os::unix::io::AsRawFd;
+    use std::sync::mpsc;
+    use std::thread;
+
+    extern "C" {
+        fn listen(sockfd: i32, backlog: i32) -> i32;
+    }
+
+    let path = env::temp_dir().join(format!("tinymio-backlog-{}.sock", process::id()));
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    // 对已经在监听的 socket 再次调用 listen 只会修改 backlog, 改小之后很快就能填满
+    assert_eq!(0, unsafe { listen(listener.as_raw_fd(), 0) });
+
+    // 不 accept, 一直连接直到 backlog 满
+    let (tx, rx) = mpsc::channel();
+    let connect_path = path.clone();
+    thread::spawn(move || {
+        let mut clients = Vec::new();
+        let err = loop {
+            match UnixStream::connect(&connect_path) {
+                Ok(client) => clients.push(client),
+                Err(e) => break e,
+            }
+            if clients.len() > 64 {
+                panic!("backlog never filled up");
+            }
+        };
+        tx.send(err.kind()).unwrap();
+    });
+    let kind = rx
+        .recv_timeout(Duration::from_secs(5))
+        .expect("connect blocked on a full backlog");
+    assert_eq!(io::ErrorKind::WouldBlock, kind);
+
+    drop(listener);
+    fs::remove_file(&path).unwrap();
+}
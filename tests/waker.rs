@@ -0,0 +1,36 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tinymio::{Events, Poll, Waker};
+
+const WAKE_TOKEN: usize = 60;
+
+//  cargo test waker -- --nocapture
+#[test]
+fn waker() {
+    let mut poll = Poll::new().unwrap();
+    let waker = Arc::new(Waker::new(&poll, WAKE_TOKEN).unwrap());
+    let mut events = Events::with_capacity(16);
+
+    // 同一个 Waker 可以在其他线程多次唤醒，Poll 不会被关闭
+    for _ in 0..3 {
+        let waker = waker.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            waker.wake().expect("wake err.");
+        });
+
+        poll.poll(&mut events, None).unwrap();
+        println!("Got events: {:?}", events);
+        assert_eq!(1, events.len());
+        assert_eq!(WAKE_TOKEN, events[0].id());
+        assert!(events[0].is_readable());
+
+        handle.join().unwrap();
+    }
+
+    // 没有唤醒时不会有多余的事件
+    poll.poll(&mut events, Some(Duration::from_millis(100)))
+        .unwrap();
+    assert!(events.is_empty());
+}
@@ -0,0 +1,46 @@
+// kqueue 每个 filter 单独返回一个事件，读写合并成一个事件是 epoll 的行为
+#![cfg(target_os = "linux")]
+
+use std::io::Write;
+use std::net::TcpListener;
+use std::time::Duration;
+use tinymio::{Events, Interests, Poll, TcpStream};
+
+const READ_TOKEN: usize = 20;
+const RW_TOKEN: usize = 21;
+
+//  cargo test writable -- --nocapture
+#[test]
+fn writable_and_combined_interests() {
+    // 本地起一个监听，不依赖 slowwly_server
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    server.write_all(b"hello").unwrap();
+
+    // 先等待数据到达客户端，保证后面合并注册时可读
+    let mut read_poll = Poll::new().unwrap();
+    read_poll
+        .registrator()
+        .register(&stream, READ_TOKEN, Interests::READABLE)
+        .unwrap();
+    let mut events = Events::with_capacity(16);
+    read_poll.poll(&mut events, None).unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(READ_TOKEN, events[0].id());
+    assert!(events[0].is_readable());
+
+    // 同一次注册同时关心读和写，事件里能区分是哪一种就绪
+    let mut poll = Poll::new().unwrap();
+    poll.registrator()
+        .register(&stream, RW_TOKEN, Interests::READABLE | Interests::WRITABLE)
+        .unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(1000)))
+        .unwrap();
+    assert_eq!(1, events.len());
+    let event = &events[0];
+    println!("Got event: {}", event.id());
+    assert_eq!(RW_TOKEN, event.id());
+    assert!(event.is_readable());
+    assert!(event.is_writable());
+}